@@ -8,15 +8,15 @@ MODE=${1:-release}
 if [ "$MODE" = "debug" ]; then
     echo "Building rustile in debug mode..."
     RUSTFLAGS="-D warnings" cargo build
-    echo "Installing to /usr/local/bin/rustile..."
-    sudo cp target/debug/rustile /usr/local/bin/rustile
-    echo "✓ Installed rustile (debug) to /usr/local/bin/rustile"
+    echo "Installing to /usr/local/bin..."
+    sudo cp target/debug/rustile target/debug/rustile-msg /usr/local/bin/
+    echo "✓ Installed rustile and rustile-msg (debug) to /usr/local/bin/"
 elif [ "$MODE" = "release" ]; then
     echo "Building rustile in release mode..."
     RUSTFLAGS="-D warnings" cargo build --release
-    echo "Installing to /usr/local/bin/rustile..."
-    sudo cp target/release/rustile /usr/local/bin/rustile
-    echo "✓ Installed rustile (release) to /usr/local/bin/rustile"
+    echo "Installing to /usr/local/bin..."
+    sudo cp target/release/rustile target/release/rustile-msg /usr/local/bin/
+    echo "✓ Installed rustile and rustile-msg (release) to /usr/local/bin/"
 else
     echo "Usage: $0 [release|debug]"
     echo "  Default: release"
@@ -0,0 +1,52 @@
+//! rustile-msg - sends a command to a running rustile over its IPC socket
+//!
+//! Usage: rustile-msg <command> [args...]
+//! Example: rustile-msg focus_next
+//! Example: rustile-msg subscribe focus_changed workspace_switched
+
+use anyhow::Result;
+use std::io::{BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::process::ExitCode;
+
+#[path = "../socket_path.rs"]
+mod socket_path;
+
+use socket_path::socket_path;
+
+/// Sends one command line and prints the response as it arrives, returning whether it was an error
+fn send(message: &str) -> Result<bool> {
+    let path = socket_path();
+    let mut stream = UnixStream::connect(&path)
+        .map_err(|e| anyhow::anyhow!("Could not connect to rustile at {:?}: {}", path, e))?;
+
+    writeln!(stream, "{message}")?;
+    stream.shutdown(Shutdown::Write)?;
+
+    // Subscriptions stream one JSON line per event, so print lines as they come in
+    let mut is_error = false;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        is_error |= line.starts_with("error");
+        println!("{line}");
+    }
+    Ok(is_error)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("Usage: rustile-msg <command> [args...]");
+        return ExitCode::from(2);
+    }
+
+    match send(&args.join(" ")) {
+        Ok(false) => ExitCode::SUCCESS,
+        Ok(true) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("rustile-msg: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
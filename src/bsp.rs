@@ -1,5 +1,6 @@
 //! Binary Space Partitioning (BSP) tree data structure for window management
 
+use serde::{Deserialize, Serialize};
 use tracing::info;
 use x11rb::protocol::xproto::Window;
 
@@ -21,12 +22,17 @@ pub mod bsp_constants {
 
     /// Modulus for alternating split directions (even=vertical, odd=horizontal)
     pub const SPLIT_DIRECTION_MODULUS: usize = 2;
+
+    /// Split ratios are clamped to this range when resizing
+    pub const MIN_RESIZE_RATIO: f32 = 0.1;
+    pub const MAX_RESIZE_RATIO: f32 = 0.9;
 }
 
 // === Types ===
 
 /// Represents a split direction in BSP layout
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SplitDirection {
     /// Horizontal arrangement: windows placed left-to-right
     Horizontal,
@@ -44,8 +50,34 @@ impl SplitDirection {
     }
 }
 
+/// Screen direction, used for resizing and for placing new windows
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Where and how a new window splits its target
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    split_count: usize,
+    split_ratio: f32,
+    side: Option<Direction>,
+}
+
+/// Outcome of a resize attempt within a subtree
+enum ResizeResult {
+    NotFound,
+    /// Window found, but no suitable split below this point
+    Pending,
+    Resized,
+}
+
 /// Represents a node in the BSP tree
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BspNode {
     /// A split with two child nodes
     Split {
@@ -59,7 +91,7 @@ pub enum BspNode {
 }
 
 /// BSP tree for managing window splits
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BspTree {
     pub(crate) root: Option<BspNode>,
     split_count: usize, // To alternate split directions
@@ -101,6 +133,33 @@ impl BspTree {
         }
     }
 
+    /// Returns the window that takes over this window's space when it is removed:
+    /// the nearest leaf of its sibling subtree
+    pub fn sibling_window(&self, window: Window) -> Option<Window> {
+        self.root
+            .as_ref()
+            .and_then(|root| Self::sibling_in_node(root, window))
+    }
+
+    /// Helper to find the sibling leaf of a window below a node
+    fn sibling_in_node(node: &BspNode, window: Window) -> Option<Window> {
+        let BspNode::Split { left, right, .. } = node else {
+            return None;
+        };
+        let leaves = |node: &BspNode| {
+            let mut windows = Vec::new();
+            Self::collect_windows_ordered(node, &mut windows);
+            windows
+        };
+        match (left.as_ref(), right.as_ref()) {
+            (BspNode::Leaf(w), _) if *w == window => leaves(right).first().copied(),
+            (_, BspNode::Leaf(w)) if *w == window => leaves(left).last().copied(),
+            _ => {
+                Self::sibling_in_node(left, window).or_else(|| Self::sibling_in_node(right, window))
+            }
+        }
+    }
+
     /// Checks if the tree contains a specific window
     pub fn has_window(&self, target_window: Window) -> bool {
         match &self.root {
@@ -143,6 +202,18 @@ impl BspTree {
 
     /// Adds a window to the BSP tree using the simplest algorithm
     pub fn add_window(&mut self, window: Window, focused_window: Option<Window>, split_ratio: f32) {
+        self.insert_window(window, focused_window, split_ratio, None);
+    }
+
+    /// Adds a window by splitting the focused window (or the last leaf if it is not tiled)
+    /// `side` puts the new window on that side of it; otherwise split directions alternate
+    pub fn insert_window(
+        &mut self,
+        window: Window,
+        focused_window: Option<Window>,
+        split_ratio: f32,
+        side: Option<Direction>,
+    ) {
         if self.root.is_none() {
             // First window - becomes root
             self.root = Some(BspNode::Leaf(window));
@@ -150,7 +221,13 @@ impl BspTree {
         }
 
         // Find where to insert the window (split the focused window or last leaf)
-        let target_window = focused_window.unwrap_or(window);
+        let target_window = match focused_window {
+            Some(focused) if self.has_window(focused) => focused,
+            _ => match self.all_windows().last() {
+                Some(&last) => last,
+                None => return,
+            },
+        };
         let split_count = self.split_count; // Capture split_count to avoid borrowing issues
 
         if let Some(ref mut root_node) = self.root {
@@ -158,8 +235,11 @@ impl BspTree {
                 root_node,
                 window,
                 target_window,
-                split_count,
-                split_ratio,
+                Placement {
+                    split_count,
+                    split_ratio,
+                    side,
+                },
             );
         }
         self.split_count += 1;
@@ -170,27 +250,39 @@ impl BspTree {
         node: &mut BspNode,
         new_window: Window,
         target_window: Window,
-        split_count: usize,
-        split_ratio: f32,
+        placement: Placement,
     ) -> bool {
         match node {
             BspNode::Leaf(existing_window) => {
                 if *existing_window == target_window {
                     // Found target - split this leaf
-                    let direction = if split_count % bsp_constants::SPLIT_DIRECTION_MODULUS == 0 {
-                        SplitDirection::Horizontal
-                    } else {
-                        SplitDirection::Vertical
+                    let (direction, new_first) = match placement.side {
+                        Some(Direction::Left) => (SplitDirection::Horizontal, true),
+                        Some(Direction::Right) => (SplitDirection::Horizontal, false),
+                        Some(Direction::Up) => (SplitDirection::Vertical, true),
+                        Some(Direction::Down) => (SplitDirection::Vertical, false),
+                        None if placement
+                            .split_count
+                            .is_multiple_of(bsp_constants::SPLIT_DIRECTION_MODULUS) =>
+                        {
+                            (SplitDirection::Horizontal, false)
+                        }
+                        None => (SplitDirection::Vertical, false),
                     };
 
                     let old_leaf = BspNode::Leaf(*existing_window);
                     let new_leaf = BspNode::Leaf(new_window);
+                    let (left, right) = if new_first {
+                        (new_leaf, old_leaf)
+                    } else {
+                        (old_leaf, new_leaf)
+                    };
 
                     *node = BspNode::Split {
                         direction,
-                        ratio: split_ratio,
-                        left: Box::new(old_leaf),
-                        right: Box::new(new_leaf),
+                        ratio: placement.split_ratio,
+                        left: Box::new(left),
+                        right: Box::new(right),
                     };
                     return true;
                 }
@@ -199,20 +291,13 @@ impl BspTree {
             BspNode::Split { left, right, .. } => {
                 // Try left subtree first
                 if Self::contains_window_static(left, target_window) {
-                    Self::insert_window_into_node_static(
-                        left,
-                        new_window,
-                        target_window,
-                        split_count,
-                        split_ratio,
-                    )
+                    Self::insert_window_into_node_static(left, new_window, target_window, placement)
                 } else if Self::contains_window_static(right, target_window) {
                     Self::insert_window_into_node_static(
                         right,
                         new_window,
                         target_window,
-                        split_count,
-                        split_ratio,
+                        placement,
                     )
                 } else {
                     false
@@ -407,42 +492,7 @@ impl BspTree {
                 }
 
                 // Calculate child rectangles and recurse
-                let (left_rect, right_rect) = match direction {
-                    SplitDirection::Horizontal => {
-                        let split_x = rect.x + (rect.width as f32 * ratio) as i32;
-                        (
-                            crate::workspace_renderer::BspRect {
-                                x: rect.x,
-                                y: rect.y,
-                                width: split_x - rect.x,
-                                height: rect.height,
-                            },
-                            crate::workspace_renderer::BspRect {
-                                x: split_x,
-                                y: rect.y,
-                                width: rect.x + rect.width - split_x,
-                                height: rect.height,
-                            },
-                        )
-                    }
-                    SplitDirection::Vertical => {
-                        let split_y = rect.y + (rect.height as f32 * ratio) as i32;
-                        (
-                            crate::workspace_renderer::BspRect {
-                                x: rect.x,
-                                y: rect.y,
-                                width: rect.width,
-                                height: split_y - rect.y,
-                            },
-                            crate::workspace_renderer::BspRect {
-                                x: rect.x,
-                                y: split_y,
-                                width: rect.width,
-                                height: rect.y + rect.height - split_y,
-                            },
-                        )
-                    }
-                };
+                let (left_rect, right_rect) = Self::split_rects(*direction, *ratio, rect);
 
                 // Try to find in left subtree
                 if Self::contains_window_static(left, target_window) {
@@ -456,6 +506,132 @@ impl BspTree {
         }
     }
 
+    /// Splits a rectangle into the left/top and right/bottom child rectangles
+    fn split_rects(
+        direction: SplitDirection,
+        ratio: f32,
+        rect: crate::workspace_renderer::BspRect,
+    ) -> (
+        crate::workspace_renderer::BspRect,
+        crate::workspace_renderer::BspRect,
+    ) {
+        match direction {
+            SplitDirection::Horizontal => {
+                let split_x = rect.x + (rect.width as f32 * ratio) as i32;
+                (
+                    crate::workspace_renderer::BspRect {
+                        x: rect.x,
+                        y: rect.y,
+                        width: split_x - rect.x,
+                        height: rect.height,
+                    },
+                    crate::workspace_renderer::BspRect {
+                        x: split_x,
+                        y: rect.y,
+                        width: rect.x + rect.width - split_x,
+                        height: rect.height,
+                    },
+                )
+            }
+            SplitDirection::Vertical => {
+                let split_y = rect.y + (rect.height as f32 * ratio) as i32;
+                (
+                    crate::workspace_renderer::BspRect {
+                        x: rect.x,
+                        y: rect.y,
+                        width: rect.width,
+                        height: split_y - rect.y,
+                    },
+                    crate::workspace_renderer::BspRect {
+                        x: rect.x,
+                        y: split_y,
+                        width: rect.width,
+                        height: rect.y + rect.height - split_y,
+                    },
+                )
+            }
+        }
+    }
+
+    /// Moves the window's edge on the given side by `pixels` (negative shrinks)
+    /// Adjusts the nearest ancestor split that has a neighbour on that side.
+    /// Returns false if the window has no neighbour in that direction.
+    pub fn resize_window(
+        &mut self,
+        window: Window,
+        direction: Direction,
+        pixels: i32,
+        screen_rect: crate::workspace_renderer::BspRect,
+    ) -> bool {
+        match &mut self.root {
+            Some(root) => matches!(
+                Self::resize_recursive(root, window, direction, pixels, screen_rect),
+                ResizeResult::Resized
+            ),
+            None => false,
+        }
+    }
+
+    /// Recursively finds the window, then adjusts the first matching split on the way up
+    fn resize_recursive(
+        node: &mut BspNode,
+        target_window: Window,
+        direction: Direction,
+        pixels: i32,
+        rect: crate::workspace_renderer::BspRect,
+    ) -> ResizeResult {
+        match node {
+            BspNode::Leaf(window) => {
+                if *window == target_window {
+                    ResizeResult::Pending
+                } else {
+                    ResizeResult::NotFound
+                }
+            }
+            BspNode::Split {
+                direction: split_direction,
+                ratio,
+                left,
+                right,
+            } => {
+                let (left_rect, right_rect) = Self::split_rects(*split_direction, *ratio, rect);
+                let (in_left, result) = if Self::contains_window_static(left, target_window) {
+                    let result =
+                        Self::resize_recursive(left, target_window, direction, pixels, left_rect);
+                    (true, result)
+                } else if Self::contains_window_static(right, target_window) {
+                    let result =
+                        Self::resize_recursive(right, target_window, direction, pixels, right_rect);
+                    (false, result)
+                } else {
+                    return ResizeResult::NotFound;
+                };
+
+                if !matches!(result, ResizeResult::Pending) {
+                    return result;
+                }
+
+                // The split's divider must lie on the requested side of the window
+                let (size, sign) = match (direction, *split_direction, in_left) {
+                    (Direction::Right, SplitDirection::Horizontal, true) => (rect.width, 1.0),
+                    (Direction::Left, SplitDirection::Horizontal, false) => (rect.width, -1.0),
+                    (Direction::Down, SplitDirection::Vertical, true) => (rect.height, 1.0),
+                    (Direction::Up, SplitDirection::Vertical, false) => (rect.height, -1.0),
+                    _ => return ResizeResult::Pending,
+                };
+                if size <= 0 {
+                    return ResizeResult::Pending;
+                }
+
+                *ratio = (*ratio + sign * pixels as f32 / size as f32).clamp(
+                    bsp_constants::MIN_RESIZE_RATIO,
+                    bsp_constants::MAX_RESIZE_RATIO,
+                );
+                ResizeResult::Resized
+            }
+        }
+    }
+
     /// Remove a window from a node, returning the replacement node (or None if should be removed)
     fn remove_window_from_node_static(node: BspNode, target_window: Window) -> Option<BspNode> {
         match node {
@@ -641,6 +817,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_insert_window_on_side() {
+        let mut bsp_tree = BspTree::new();
+        bsp_tree.add_window(1, None, 0.5);
+        bsp_tree.insert_window(2, Some(1), 0.3, Some(Direction::Up));
+
+        let Some(BspNode::Split {
+            direction,
+            ratio,
+            left,
+            right,
+        }) = &bsp_tree.root
+        else {
+            panic!("Root should be a split node");
+        };
+        assert!(matches!(direction, SplitDirection::Vertical));
+        assert!((ratio - 0.3).abs() < f32::EPSILON);
+        assert!(matches!(left.as_ref(), BspNode::Leaf(2)));
+        assert!(matches!(right.as_ref(), BspNode::Leaf(1)));
+
+        // A target outside the tree (a floating window) splits the last leaf instead
+        bsp_tree.insert_window(3, Some(99), 0.5, Some(Direction::Right));
+        assert_eq!(bsp_tree.all_windows(), vec![2, 1, 3]);
+    }
+
     #[test]
     fn test_bsp_window_removal() {
         let mut bsp_tree = BspTree::new();
@@ -665,6 +866,23 @@ mod tests {
         assert!(bsp_tree.root.is_none());
     }
 
+    #[test]
+    fn test_sibling_window() {
+        let mut bsp_tree = BspTree::new();
+        assert_eq!(bsp_tree.sibling_window(1), None);
+
+        // 1 | (2 / 3)
+        bsp_tree.add_window(1, None, 0.5);
+        assert_eq!(bsp_tree.sibling_window(1), None);
+        bsp_tree.add_window(2, Some(1), 0.5);
+        bsp_tree.add_window(3, Some(2), 0.5);
+
+        assert_eq!(bsp_tree.sibling_window(3), Some(2));
+        assert_eq!(bsp_tree.sibling_window(2), Some(3));
+        // Window 1's sibling is the (2 / 3) split, whose nearest leaf is 2
+        assert_eq!(bsp_tree.sibling_window(1), Some(2));
+    }
+
     #[test]
     fn test_bsp_contains_window() {
         let mut bsp_tree = BspTree::new();
@@ -829,6 +1047,51 @@ mod tests {
 
     // === Balance Tests ===
 
+    #[test]
+    fn test_resize_window() {
+        use crate::workspace_renderer::BspRect;
+
+        let screen_rect = BspRect {
+            x: 0,
+            y: 0,
+            width: 1000,
+            height: 800,
+        };
+
+        //     Split(H)
+        //    /        \
+        //   10      Split(V)
+        //          /        \
+        //         20        30
+        let mut bsp_tree = BspTree::new();
+        bsp_tree.add_window(10, None, 0.5);
+        bsp_tree.add_window(20, Some(10), 0.5);
+        bsp_tree.add_window(30, Some(20), 0.5);
+
+        let root_ratio = |tree: &BspTree| match &tree.root {
+            Some(BspNode::Split { ratio, .. }) => *ratio,
+            _ => panic!("Expected split root"),
+        };
+
+        // Growing 10 to the right moves the root divider by 100/1000
+        assert!(bsp_tree.resize_window(10, Direction::Right, 100, screen_rect));
+        assert!((root_ratio(&bsp_tree) - 0.6).abs() < 0.001);
+
+        // 30 has no neighbour below, and nothing to the right
+        assert!(!bsp_tree.resize_window(30, Direction::Down, 50, screen_rect));
+        assert!(!bsp_tree.resize_window(30, Direction::Right, 50, screen_rect));
+
+        // 30 growing left skips the vertical split and moves the root divider back
+        assert!(bsp_tree.resize_window(30, Direction::Left, 100, screen_rect));
+        assert!((root_ratio(&bsp_tree) - 0.5).abs() < 0.001);
+
+        // Ratios are clamped
+        assert!(bsp_tree.resize_window(10, Direction::Right, 5000, screen_rect));
+        assert!((root_ratio(&bsp_tree) - bsp_constants::MAX_RESIZE_RATIO).abs() < 0.001);
+
+        assert!(!bsp_tree.resize_window(999, Direction::Left, 10, screen_rect));
+    }
+
     #[test]
     fn test_balance_empty_tree() {
         let mut bsp_tree = BspTree::new();
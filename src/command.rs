@@ -0,0 +1,659 @@
+//! Typed window manager commands shared by key bindings, IPC and hooks
+//!
+//! A binding value is a chain of actions separated by `;`, where each action is a
+//! command or `if <condition> then <command> [else <command>]`.
+
+use anyhow::Result;
+use std::fmt;
+use tracing::warn;
+
+use crate::bsp::Direction;
+
+/// Workspace addressed by index (as reported by `get_workspaces`) or by name
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkspaceTarget {
+    Index(usize),
+    Name(String),
+}
+
+/// A parsed command with its arguments
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    FocusNext,
+    FocusPrev,
+    /// Focuses the previously focused window, toggling between the two most recent
+    FocusLast,
+    /// Walks the recently used windows while the binding's modifiers are held
+    CycleNext,
+    CyclePrev,
+    SwapWindowNext,
+    SwapWindowPrev,
+    DestroyWindow,
+    ToggleFullscreen,
+    RotateWindows,
+    ToggleZoom,
+    /// Keeps the focused window floating on every workspace, or stops doing so
+    ToggleSticky,
+    BalanceTree,
+    Resize(Direction, i32),
+    CreateWorkspace(Option<String>),
+    DeleteWorkspace,
+    SwitchWorkspaceNext,
+    SwitchWorkspacePrev,
+    SwitchWorkspace(WorkspaceTarget),
+    MoveWindowToWorkspace(WorkspaceTarget),
+    /// Hides the focused window in the scratchpad
+    MoveToScratchpad,
+    /// Shows or hides a scratchpad window, optionally the one with this class
+    ScratchpadShow(Option<String>),
+    /// Switches the key binding mode (`default` leaves any mode)
+    Mode(String),
+    ReloadConfig,
+    Restart,
+    Shutdown,
+    /// Shell command line to launch
+    Exec(String),
+}
+
+/// Names of the built-in commands, checked for typos of unknown names
+const NAMES: &[&str] = &[
+    "exec",
+    "focus_next",
+    "focus_prev",
+    "focus_last",
+    "cycle_next",
+    "cycle_prev",
+    "swap_window_next",
+    "swap_window_prev",
+    "destroy_window",
+    "toggle_fullscreen",
+    "rotate_windows",
+    "toggle_zoom",
+    "toggle_sticky",
+    "balance_tree",
+    "resize",
+    "create_workspace",
+    "delete_workspace",
+    "switch_workspace_next",
+    "switch_workspace_prev",
+    "switch_workspace",
+    "move_window_to_workspace",
+    "move_to_scratchpad",
+    "scratchpad_show",
+    "mode",
+    "reload_config",
+    "restart",
+    "shutdown",
+];
+
+impl Command {
+    /// Parses a command line such as `resize left 20`, `switch_workspace 3` or `exec firefox`
+    ///
+    /// Programs are launched with `exec`. A name that is a near miss of a built-in command
+    /// is rejected as a typo; other unknown names still launch as programs, with a warning.
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let (name, rest) = match line.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, rest.trim()),
+            None => (line, ""),
+        };
+        let args: Vec<&str> = rest.split_whitespace().collect();
+
+        let command = match name {
+            "" => return Err(anyhow::anyhow!("Empty command")),
+            "exec" => {
+                if rest.is_empty() {
+                    return Err(anyhow::anyhow!("exec needs a command line"));
+                }
+                return Ok(Command::Exec(rest.to_string()));
+            }
+            "focus_next" => Command::FocusNext,
+            "focus_prev" => Command::FocusPrev,
+            "focus_last" => Command::FocusLast,
+            "cycle_next" => Command::CycleNext,
+            "cycle_prev" => Command::CyclePrev,
+            "swap_window_next" => Command::SwapWindowNext,
+            "swap_window_prev" => Command::SwapWindowPrev,
+            "destroy_window" => Command::DestroyWindow,
+            "toggle_fullscreen" => Command::ToggleFullscreen,
+            "rotate_windows" => Command::RotateWindows,
+            "toggle_zoom" => Command::ToggleZoom,
+            "toggle_sticky" => Command::ToggleSticky,
+            "balance_tree" => Command::BalanceTree,
+            "resize" => return Self::parse_resize(&args),
+            "create_workspace" => {
+                return match args.as_slice() {
+                    [] => Ok(Command::CreateWorkspace(None)),
+                    [name] => Ok(Command::CreateWorkspace(Some(name.to_string()))),
+                    _ => Err(anyhow::anyhow!("Usage: create_workspace [name]")),
+                };
+            }
+            "delete_workspace" => Command::DeleteWorkspace,
+            "switch_workspace_next" => Command::SwitchWorkspaceNext,
+            "switch_workspace_prev" => Command::SwitchWorkspacePrev,
+            "switch_workspace" => {
+                return Ok(Command::SwitchWorkspace(Self::parse_target(name, &args)?));
+            }
+            "move_window_to_workspace" => {
+                return Ok(Command::MoveWindowToWorkspace(Self::parse_target(
+                    name, &args,
+                )?));
+            }
+            "move_to_scratchpad" => Command::MoveToScratchpad,
+            "scratchpad_show" => {
+                return match args.as_slice() {
+                    [] => Ok(Command::ScratchpadShow(None)),
+                    [class] => Ok(Command::ScratchpadShow(Some(class.to_string()))),
+                    _ => Err(anyhow::anyhow!("Usage: scratchpad_show [class]")),
+                };
+            }
+            "mode" => {
+                return match args.as_slice() {
+                    [mode] => Ok(Command::Mode(mode.to_string())),
+                    _ => Err(anyhow::anyhow!("Usage: mode <name|default>")),
+                };
+            }
+            "reload_config" => Command::ReloadConfig,
+            "restart" => Command::Restart,
+            "shutdown" => Command::Shutdown,
+            _ => {
+                if let Some(suggestion) = Self::suggest(name) {
+                    return Err(anyhow::anyhow!(
+                        "Unknown command: {} (did you mean {}?)",
+                        name,
+                        suggestion
+                    ));
+                }
+                warn!(
+                    "Launching \"{}\" without exec is deprecated, use \"exec {}\"",
+                    line, line
+                );
+                return Ok(Command::Exec(line.to_string()));
+            }
+        };
+
+        if !args.is_empty() {
+            return Err(anyhow::anyhow!("{} takes no arguments", name));
+        }
+        Ok(command)
+    }
+
+    /// Parses `resize <left|right|up|down> <pixels>`
+    fn parse_resize(args: &[&str]) -> Result<Self> {
+        let usage = || anyhow::anyhow!("Usage: resize <left|right|up|down> <pixels>");
+        let [direction, pixels] = args else {
+            return Err(usage());
+        };
+        let direction = match *direction {
+            "left" => Direction::Left,
+            "right" => Direction::Right,
+            "up" => Direction::Up,
+            "down" => Direction::Down,
+            _ => return Err(usage()),
+        };
+        let pixels = pixels.parse().map_err(|_| usage())?;
+        Ok(Command::Resize(direction, pixels))
+    }
+
+    /// Parses a single `<index|name>` workspace argument
+    fn parse_target(name: &str, args: &[&str]) -> Result<WorkspaceTarget> {
+        match args {
+            [target] => Ok(match target.parse() {
+                Ok(index) => WorkspaceTarget::Index(index),
+                Err(_) => WorkspaceTarget::Name(target.to_string()),
+            }),
+            _ => Err(anyhow::anyhow!("Usage: {} <index|name>", name)),
+        }
+    }
+
+    /// Finds the built-in command closest to `name`, if it is close enough to be a typo
+    /// Only multi-word commands count as near misses, since ones like `node` are programs
+    /// close to `mode`; a built-in with a misspelt suffix (`resize_rigth`) is caught too.
+    fn suggest(name: &str) -> Option<&'static str> {
+        let name = name.to_lowercase().replace('-', "_");
+        NAMES
+            .iter()
+            .filter(|known| known.contains('_'))
+            .map(|&known| (edit_distance(&name, known), known))
+            .filter(|&(distance, known)| distance <= 2 && distance < known.len() / 2)
+            .min_by_key(|&(distance, _)| distance)
+            .map(|(_, known)| known)
+            .or_else(|| {
+                NAMES
+                    .iter()
+                    .copied()
+                    .filter(|known| name.starts_with(&format!("{known}_")))
+                    .max_by_key(|known| known.len())
+            })
+    }
+}
+
+/// Levenshtein distance: single-character insertions, deletions and substitutions
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// State checked by conditional actions, always about the current workspace
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// The focused window is fullscreen
+    Fullscreen,
+    /// The focused window is zoomed
+    Zoomed,
+    /// Some window has focus
+    Focused,
+    /// The workspace has no windows
+    Empty,
+    Not(Box<Condition>),
+}
+
+/// One step of a command chain
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Run(Command),
+    If {
+        condition: Condition,
+        then: Command,
+        otherwise: Option<Command>,
+    },
+}
+
+/// Actions run in sequence by a single binding or IPC request
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandChain(pub Vec<Action>);
+
+impl Condition {
+    /// Parses `fullscreen`, `zoomed`, `focused`, `empty`, optionally prefixed by `not`
+    fn parse(text: &str) -> Result<Self> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["not", rest @ ..] => Ok(Condition::Not(Box::new(Self::parse(&rest.join(" "))?))),
+            ["fullscreen"] => Ok(Condition::Fullscreen),
+            ["zoomed"] => Ok(Condition::Zoomed),
+            ["focused"] => Ok(Condition::Focused),
+            ["empty"] => Ok(Condition::Empty),
+            _ => Err(anyhow::anyhow!(
+                "Unknown condition: {:?} (expected fullscreen, zoomed, focused or empty)",
+                text
+            )),
+        }
+    }
+}
+
+impl Action {
+    /// Parses a single command or an `if ... then ... [else ...]` action
+    fn parse(text: &str) -> Result<Self> {
+        let Some(rest) = text.strip_prefix("if ") else {
+            return Ok(Action::Run(Command::parse(text)?));
+        };
+
+        let usage = || anyhow::anyhow!("Usage: if <condition> then <command> [else <command>]");
+        let (condition, branches) = split_keyword(rest, "then").ok_or_else(usage)?;
+        let (then, otherwise) = match split_keyword(branches, "else") {
+            Some((then, otherwise)) => (then, Some(Command::parse(otherwise)?)),
+            None => (branches, None),
+        };
+
+        Ok(Action::If {
+            condition: Condition::parse(condition)?,
+            then: Command::parse(then)?,
+            otherwise,
+        })
+    }
+}
+
+impl CommandChain {
+    /// Parses `;`-separated actions; `;` inside quotes is left to the shell
+    pub fn parse(line: &str) -> Result<Self> {
+        let actions = split_unquoted(line, ';')
+            .into_iter()
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(Action::parse)
+            .collect::<Result<Vec<_>>>()?;
+
+        if actions.is_empty() {
+            return Err(anyhow::anyhow!("Empty command"));
+        }
+        Ok(CommandChain(actions))
+    }
+
+    /// Iterates over every command the chain may run, including both branches of conditionals
+    pub fn commands(&self) -> impl Iterator<Item = &Command> {
+        self.0.iter().flat_map(|action| match action {
+            Action::Run(command) => vec![command],
+            Action::If {
+                then, otherwise, ..
+            } => std::iter::once(then).chain(otherwise).collect(),
+        })
+    }
+}
+
+/// Splits on the first standalone occurrence of `keyword`
+fn split_keyword<'a>(text: &'a str, keyword: &str) -> Option<(&'a str, &'a str)> {
+    let mut offset = 0;
+    for word in text.split_inclusive(char::is_whitespace) {
+        if word.trim() == keyword {
+            let before = text[..offset].trim();
+            let after = text[offset + word.len()..].trim();
+            return Some((before, after));
+        }
+        offset += word.len();
+    }
+    None
+}
+
+/// Splits `text` on `separator`, ignoring separators inside single or double quotes
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, _) if c == separator => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::FocusNext => write!(f, "focus_next"),
+            Command::FocusPrev => write!(f, "focus_prev"),
+            Command::FocusLast => write!(f, "focus_last"),
+            Command::CycleNext => write!(f, "cycle_next"),
+            Command::CyclePrev => write!(f, "cycle_prev"),
+            Command::SwapWindowNext => write!(f, "swap_window_next"),
+            Command::SwapWindowPrev => write!(f, "swap_window_prev"),
+            Command::DestroyWindow => write!(f, "destroy_window"),
+            Command::ToggleFullscreen => write!(f, "toggle_fullscreen"),
+            Command::RotateWindows => write!(f, "rotate_windows"),
+            Command::ToggleZoom => write!(f, "toggle_zoom"),
+            Command::ToggleSticky => write!(f, "toggle_sticky"),
+            Command::BalanceTree => write!(f, "balance_tree"),
+            Command::Resize(direction, pixels) => {
+                write!(
+                    f,
+                    "resize {} {}",
+                    format!("{direction:?}").to_lowercase(),
+                    pixels
+                )
+            }
+            Command::CreateWorkspace(None) => write!(f, "create_workspace"),
+            Command::CreateWorkspace(Some(name)) => write!(f, "create_workspace {name}"),
+            Command::DeleteWorkspace => write!(f, "delete_workspace"),
+            Command::SwitchWorkspaceNext => write!(f, "switch_workspace_next"),
+            Command::SwitchWorkspacePrev => write!(f, "switch_workspace_prev"),
+            Command::SwitchWorkspace(target) => write!(f, "switch_workspace {target}"),
+            Command::MoveWindowToWorkspace(target) => {
+                write!(f, "move_window_to_workspace {target}")
+            }
+            Command::MoveToScratchpad => write!(f, "move_to_scratchpad"),
+            Command::ScratchpadShow(None) => write!(f, "scratchpad_show"),
+            Command::ScratchpadShow(Some(class)) => write!(f, "scratchpad_show {class}"),
+            Command::Mode(mode) => write!(f, "mode {mode}"),
+            Command::ReloadConfig => write!(f, "reload_config"),
+            Command::Restart => write!(f, "restart"),
+            Command::Shutdown => write!(f, "shutdown"),
+            Command::Exec(line) => write!(f, "exec {line}"),
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::Fullscreen => write!(f, "fullscreen"),
+            Condition::Zoomed => write!(f, "zoomed"),
+            Condition::Focused => write!(f, "focused"),
+            Condition::Empty => write!(f, "empty"),
+            Condition::Not(condition) => write!(f, "not {condition}"),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Run(command) => write!(f, "{command}"),
+            Action::If {
+                condition,
+                then,
+                otherwise: None,
+            } => write!(f, "if {condition} then {then}"),
+            Action::If {
+                condition,
+                then,
+                otherwise: Some(otherwise),
+            } => write!(f, "if {condition} then {then} else {otherwise}"),
+        }
+    }
+}
+
+impl fmt::Display for CommandChain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, action) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{action}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for WorkspaceTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkspaceTarget::Index(index) => write!(f, "{index}"),
+            WorkspaceTarget::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_simple_commands() {
+        assert_eq!(Command::parse("focus_next").unwrap(), Command::FocusNext);
+        assert_eq!(
+            Command::parse("  balance_tree ").unwrap(),
+            Command::BalanceTree
+        );
+        assert!(Command::parse("focus_next now").is_err());
+        assert_eq!(Command::parse("cycle_prev").unwrap(), Command::CyclePrev);
+        assert_eq!(Command::parse("focus_last").unwrap(), Command::FocusLast);
+        assert_eq!(
+            Command::parse("toggle_sticky").unwrap(),
+            Command::ToggleSticky
+        );
+    }
+
+    #[test]
+    fn test_parse_commands_with_arguments() {
+        assert_eq!(
+            Command::parse("resize left 20").unwrap(),
+            Command::Resize(Direction::Left, 20)
+        );
+        assert_eq!(
+            Command::parse("resize down -15").unwrap(),
+            Command::Resize(Direction::Down, -15)
+        );
+        assert_eq!(
+            Command::parse("switch_workspace 3").unwrap(),
+            Command::SwitchWorkspace(WorkspaceTarget::Index(3))
+        );
+        assert_eq!(
+            Command::parse("move_window_to_workspace web").unwrap(),
+            Command::MoveWindowToWorkspace(WorkspaceTarget::Name("web".to_string()))
+        );
+        assert_eq!(
+            Command::parse("create_workspace mail").unwrap(),
+            Command::CreateWorkspace(Some("mail".to_string()))
+        );
+        assert_eq!(
+            Command::parse("scratchpad_show scratch-term").unwrap(),
+            Command::ScratchpadShow(Some("scratch-term".to_string()))
+        );
+
+        assert!(Command::parse("resize sideways 20").is_err());
+        assert!(Command::parse("resize left").is_err());
+        assert!(Command::parse("switch_workspace").is_err());
+        assert!(Command::parse("scratchpad_show a b").is_err());
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(
+            Command::parse("mode resize").unwrap(),
+            Command::Mode("resize".to_string())
+        );
+        assert!(Command::parse("mode").is_err());
+
+        let chain = CommandChain::parse("if zoomed then mode resize else mode default").unwrap();
+        let modes: Vec<_> = chain.commands().map(Command::to_string).collect();
+        assert_eq!(modes, ["mode resize", "mode default"]);
+    }
+
+    #[test]
+    fn test_parse_exec() {
+        assert_eq!(
+            Command::parse("exec firefox --new-window").unwrap(),
+            Command::Exec("firefox --new-window".to_string())
+        );
+        // Bare program names still launch, with a deprecation warning
+        assert_eq!(
+            Command::parse("google-chrome --disable-gpu").unwrap(),
+            Command::Exec("google-chrome --disable-gpu".to_string())
+        );
+        assert_eq!(
+            Command::parse("node server.js").unwrap(),
+            Command::Exec("node server.js".to_string())
+        );
+        assert!(Command::parse("exec").is_err());
+    }
+
+    #[test]
+    fn test_unknown_internal_command_is_rejected() {
+        assert!(Command::parse("focus_nxt").is_err());
+        assert!(Command::parse("").is_err());
+
+        let error = Command::parse("resize_rigth 20").unwrap_err();
+        assert!(
+            error.to_string().contains("did you mean resize?"),
+            "{error}"
+        );
+
+        for typo in ["focus_nxt", "focusnext", "focus-next", "Focus_Next"] {
+            let error = Command::parse(typo).unwrap_err();
+            assert!(
+                error.to_string().contains("did you mean focus_next?"),
+                "{error}"
+            );
+        }
+        // Names that are not near misses of a built-in are programs
+        assert_eq!(
+            Command::parse("my_script").unwrap(),
+            Command::Exec("my_script".to_string())
+        );
+    }
+
+    #[test]
+    fn test_every_name_is_a_command() {
+        for name in NAMES {
+            if let Err(error) = Command::parse(name) {
+                assert!(!error.to_string().starts_with("Unknown command"), "{error}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_display_roundtrip() {
+        for line in [
+            "resize up 30",
+            "switch_workspace web",
+            "create_workspace",
+            "scratchpad_show",
+            "scratchpad_show notes",
+            "exec xterm -e top",
+        ] {
+            let command = Command::parse(line).unwrap();
+            assert_eq!(Command::parse(&command.to_string()).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn test_parse_chain() {
+        let chain = CommandChain::parse("create_workspace; exec alacritty").unwrap();
+        assert_eq!(
+            chain.0,
+            vec![
+                Action::Run(Command::CreateWorkspace(None)),
+                Action::Run(Command::Exec("alacritty".to_string())),
+            ]
+        );
+
+        // Semicolons inside quotes belong to the shell command
+        let chain = CommandChain::parse("exec sh -c 'echo a; echo b'; toggle_zoom").unwrap();
+        assert_eq!(chain.0.len(), 2);
+        assert_eq!(
+            chain.0[0],
+            Action::Run(Command::Exec("sh -c 'echo a; echo b'".to_string()))
+        );
+
+        assert!(CommandChain::parse(" ; ").is_err());
+        assert!(CommandChain::parse("toggle_zoom; focus_nxt").is_err());
+    }
+
+    #[test]
+    fn test_parse_conditional() {
+        let chain =
+            CommandChain::parse("if fullscreen then toggle_fullscreen else toggle_zoom").unwrap();
+        assert_eq!(
+            chain.0,
+            vec![Action::If {
+                condition: Condition::Fullscreen,
+                then: Command::ToggleFullscreen,
+                otherwise: Some(Command::ToggleZoom),
+            }]
+        );
+
+        let chain = CommandChain::parse("if not empty then balance_tree").unwrap();
+        assert_eq!(
+            chain.0,
+            vec![Action::If {
+                condition: Condition::Not(Box::new(Condition::Empty)),
+                then: Command::BalanceTree,
+                otherwise: None,
+            }]
+        );
+
+        assert!(CommandChain::parse("if fullscreen toggle_zoom").is_err());
+        assert!(CommandChain::parse("if maximized then toggle_zoom").is_err());
+
+        // Display output parses back to the same chain
+        let line = "if not focused then exec xterm else toggle_zoom; balance_tree";
+        let chain = CommandChain::parse(line).unwrap();
+        assert_eq!(CommandChain::parse(&chain.to_string()).unwrap(), chain);
+    }
+}
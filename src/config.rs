@@ -5,6 +5,13 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use tracing::info;
 
+use crate::command::{Command, CommandChain};
+use crate::events::WmEvent;
+use crate::rules::{Geometry, WindowRule};
+
+/// Name of the mode holding the top-level `[shortcuts]`
+pub const DEFAULT_MODE: &str = "default";
+
 // === Validation Traits and Utilities ===
 
 /// Trait for validating configuration values
@@ -80,6 +87,156 @@ pub struct Config {
     pub shortcuts: HashMap<String, String>,
     /// Layout configuration
     pub layout: LayoutConfig,
+    /// General window manager behavior
+    #[serde(default)]
+    pub general: GeneralConfig,
+    /// Named key binding modes, entered with `mode <name>`
+    #[serde(default)]
+    pub modes: HashMap<String, ModeConfig>,
+    /// Window rules, applied in order when a window is mapped
+    #[serde(default)]
+    pub rules: Vec<WindowRule>,
+    /// Programs launched with the window manager
+    #[serde(default)]
+    pub autostart: AutostartConfig,
+    /// Commands run when window manager events happen
+    #[serde(default)]
+    pub hooks: HooksConfig,
+    /// Named scratchpads by window class, launched by `scratchpad_show <class>`
+    #[serde(default)]
+    pub scratchpads: HashMap<String, ScratchpadConfig>,
+}
+
+/// Programs launched by rustile itself, instead of racing it from .xinitrc
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct AutostartConfig {
+    /// Launched once when rustile starts (not after `restart`)
+    #[serde(default)]
+    pub exec: Vec<String>,
+    /// Launched when rustile starts or restarts and on every config reload
+    #[serde(default)]
+    pub exec_always: Vec<String>,
+    /// Launched the first time the named workspace is created; their windows open there
+    #[serde(default)]
+    pub workspaces: HashMap<String, Vec<String>>,
+}
+
+/// Commands run on window manager events, with the event described in RUSTILE_* variables
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HooksConfig {
+    /// Run when a new window is managed
+    #[serde(default)]
+    pub on_window_map: Option<String>,
+    /// Run when focus moves to another window (or to none)
+    #[serde(default)]
+    pub on_focus_change: Option<String>,
+    /// Run when another workspace is shown
+    #[serde(default)]
+    pub on_workspace_switch: Option<String>,
+    /// Run when a window enters or leaves fullscreen
+    #[serde(default)]
+    pub on_fullscreen: Option<String>,
+    /// Run after a swap, rotate, balance or resize
+    #[serde(default)]
+    pub on_layout_change: Option<String>,
+    /// Run when the window manager shuts down (not on restart)
+    #[serde(default)]
+    pub on_shutdown: Option<String>,
+}
+
+impl HooksConfig {
+    /// Returns the hook command to run for an event, if one is configured
+    pub fn command(&self, event: &WmEvent) -> Option<&str> {
+        let hook = match event {
+            WmEvent::WindowMapped { .. } => &self.on_window_map,
+            WmEvent::FocusChanged { .. } => &self.on_focus_change,
+            WmEvent::WorkspaceSwitched { .. } => &self.on_workspace_switch,
+            WmEvent::FullscreenToggled { .. } => &self.on_fullscreen,
+            WmEvent::LayoutChanged { .. } => &self.on_layout_change,
+            WmEvent::Shutdown => &self.on_shutdown,
+            _ => return None,
+        };
+        hook.as_deref()
+    }
+}
+
+/// A scratchpad program, launched when `scratchpad_show` finds no window of its class
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ScratchpadConfig {
+    /// Shell command that opens a window with the scratchpad's class or instance
+    pub command: String,
+    /// Size (and optional position) when shown; centered at 60% of the screen by default
+    #[serde(default)]
+    pub geometry: Option<Geometry>,
+}
+
+/// Key bindings active while a mode is entered
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ModeConfig {
+    /// Bindings of this mode; keys usually need no modifiers since the keyboard is grabbed
+    #[serde(default)]
+    pub shortcuts: HashMap<String, String>,
+    /// Focused border color while the mode is active (defaults to layout.focused_border_color)
+    #[serde(default)]
+    pub focused_border_color: Option<u32>,
+}
+
+/// General behavior configuration
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GeneralConfig {
+    /// Shell used to run launcher commands (invoked as `<shell> -c <command>`)
+    #[serde(default = "default_shell")]
+    pub shell: String,
+    /// How long a key sequence like "Super+w h" waits for its next key, in milliseconds
+    #[serde(default = "default_sequence_timeout_ms")]
+    pub sequence_timeout_ms: u64,
+    /// What takes focus after the focused window closes: mru, sibling, first or none
+    #[serde(default)]
+    pub focus_on_close: FocusOnClose,
+    /// Whether new windows take focus: always, never or if_same_workspace
+    #[serde(default)]
+    pub focus_new_windows: FocusNewWindows,
+}
+
+/// What takes focus after the focused window closes or leaves its workspace
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FocusOnClose {
+    /// The window focused before it
+    #[default]
+    Mru,
+    /// The window that takes over its space in the layout
+    Sibling,
+    /// The first window in layout order
+    First,
+    /// Nothing until the user picks a window
+    None,
+}
+
+/// Whether a newly mapped window takes focus from the one being used
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum FocusNewWindows {
+    /// Every new window is focused
+    #[default]
+    Always,
+    /// New windows never take focus, they are marked urgent instead
+    Never,
+    /// Only windows opening on the current workspace are focused
+    IfSameWorkspace,
+}
+
+impl FocusNewWindows {
+    /// Returns true if a window opening on the current workspace (or not) may take focus
+    pub fn allows(self, on_current_workspace: bool) -> bool {
+        match self {
+            FocusNewWindows::Always => true,
+            FocusNewWindows::Never => false,
+            FocusNewWindows::IfSameWorkspace => on_current_workspace,
+        }
+    }
 }
 
 /// Layout-related configuration
@@ -102,6 +259,9 @@ pub struct LayoutConfig {
     pub focused_border_color: u32,
     /// Unfocused window border color (hex format, e.g., 0x808080 for gray)
     pub unfocused_border_color: u32,
+    /// Border color of windows that demand attention (hex format, e.g., 0xFFA500 for orange)
+    #[serde(default = "default_urgent_border_color")]
+    pub urgent_border_color: u32,
 }
 
 fn default_bsp_split_ratio() -> f32 {
@@ -116,14 +276,26 @@ fn default_min_window_height() -> u32 {
     50 // Default minimum height - can be customized in config
 }
 
+fn default_urgent_border_color() -> u32 {
+    0xFFA500 // Orange
+}
+
+fn default_shell() -> String {
+    "/bin/sh".to_string()
+}
+
+fn default_sequence_timeout_ms() -> u64 {
+    1000
+}
+
 impl Default for Config {
     fn default() -> Self {
         let mut shortcuts = HashMap::new();
 
         // Default application shortcuts
-        shortcuts.insert("Shift+Alt+1".to_string(), "xterm".to_string());
-        shortcuts.insert("Shift+Alt+2".to_string(), "emacs".to_string());
-        shortcuts.insert("Shift+Alt+3".to_string(), "google-chrome".to_string());
+        shortcuts.insert("Shift+Alt+1".to_string(), "exec xterm".to_string());
+        shortcuts.insert("Shift+Alt+2".to_string(), "exec emacs".to_string());
+        shortcuts.insert("Shift+Alt+3".to_string(), "exec google-chrome".to_string());
 
         // Default window management shortcuts
         shortcuts.insert("Alt+j".to_string(), "focus_next".to_string());
@@ -138,6 +310,23 @@ impl Default for Config {
         Self {
             shortcuts,
             layout: LayoutConfig::default(),
+            general: GeneralConfig::default(),
+            modes: HashMap::new(),
+            rules: Vec::new(),
+            autostart: AutostartConfig::default(),
+            hooks: HooksConfig::default(),
+            scratchpads: HashMap::new(),
+        }
+    }
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        Self {
+            shell: default_shell(),
+            sequence_timeout_ms: default_sequence_timeout_ms(),
+            focus_on_close: FocusOnClose::default(),
+            focus_new_windows: FocusNewWindows::default(),
         }
     }
 }
@@ -152,6 +341,7 @@ impl Default for LayoutConfig {
             border_width: 5,                  // 5px for visible borders
             focused_border_color: 0xFF0000,   // Red
             unfocused_border_color: 0x808080, // Gray
+            urgent_border_color: default_urgent_border_color(),
         }
     }
 }
@@ -183,25 +373,139 @@ impl Validate for LayoutConfig {
     }
 }
 
+impl Validate for GeneralConfig {
+    fn validate(&self) -> Result<()> {
+        if self.shell.trim().is_empty() {
+            return Err(anyhow::anyhow!("general.shell must not be empty"));
+        }
+        if !(1..=60_000).contains(&self.sequence_timeout_ms) {
+            return Err(anyhow::anyhow!(
+                "general.sequence_timeout_ms must be between 1 and 60000, got: {}",
+                self.sequence_timeout_ms
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Validate for AutostartConfig {
+    fn validate(&self) -> Result<()> {
+        for (name, commands) in &self.workspaces {
+            if name.trim().is_empty() {
+                return Err(anyhow::anyhow!(
+                    "autostart.workspaces has an empty workspace name"
+                ));
+            }
+            if name.parse::<usize>().is_ok() {
+                return Err(anyhow::anyhow!(
+                    "autostart.workspaces.{:?} looks like an index; only named workspaces autostart",
+                    name
+                ));
+            }
+            if commands.iter().any(|command| command.trim().is_empty()) {
+                return Err(anyhow::anyhow!(
+                    "Empty command in autostart.workspaces.{}",
+                    name
+                ));
+            }
+        }
+        for (list, commands) in [("exec", &self.exec), ("exec_always", &self.exec_always)] {
+            if commands.iter().any(|command| command.trim().is_empty()) {
+                return Err(anyhow::anyhow!("Empty command in autostart.{}", list));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for HooksConfig {
+    fn validate(&self) -> Result<()> {
+        let hooks = [
+            ("on_window_map", &self.on_window_map),
+            ("on_focus_change", &self.on_focus_change),
+            ("on_workspace_switch", &self.on_workspace_switch),
+            ("on_fullscreen", &self.on_fullscreen),
+            ("on_layout_change", &self.on_layout_change),
+            ("on_shutdown", &self.on_shutdown),
+        ];
+        for (name, command) in hooks {
+            if command.as_ref().is_some_and(|c| c.trim().is_empty()) {
+                return Err(anyhow::anyhow!("Empty command in hooks.{}", name));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Validate for Config {
     fn validate(&self) -> Result<()> {
         // Validate sub-configurations
         self.layout.validate()?;
+        self.general.validate()?;
 
-        // Validate shortcuts
-        for (key_combo, command) in &self.shortcuts {
-            if key_combo.is_empty() {
-                return Err(anyhow::anyhow!("Empty key combination"));
+        if self.modes.contains_key(DEFAULT_MODE) {
+            return Err(anyhow::anyhow!(
+                "Mode name {:?} is reserved for the normal bindings",
+                DEFAULT_MODE
+            ));
+        }
+
+        // Validate shortcuts, including those of every mode
+        let mode_shortcuts = self.modes.values().map(|mode| &mode.shortcuts);
+        for shortcuts in std::iter::once(&self.shortcuts).chain(mode_shortcuts) {
+            for (key_combo, command) in shortcuts {
+                self.validate_shortcut(key_combo, command)?;
             }
-            if command.is_empty() {
-                return Err(anyhow::anyhow!("Empty command for key: {}", key_combo));
+        }
+
+        self.autostart.validate()?;
+        self.hooks.validate()?;
+        for (class, scratchpad) in &self.scratchpads {
+            if class.trim().is_empty() {
+                return Err(anyhow::anyhow!("scratchpads has an empty class name"));
             }
+            if scratchpad.command.trim().is_empty() {
+                return Err(anyhow::anyhow!("Empty command in scratchpads.{}", class));
+            }
+        }
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            rule.validate()
+                .map_err(|e| anyhow::anyhow!("Invalid window rule {}: {}", index + 1, e))?;
         }
 
         Ok(())
     }
 }
 
+impl Config {
+    /// Checks that a binding parses and only enters modes that exist
+    fn validate_shortcut(&self, key_combo: &str, command: &str) -> Result<()> {
+        if key_combo.is_empty() {
+            return Err(anyhow::anyhow!("Empty key combination"));
+        }
+        if command.is_empty() {
+            return Err(anyhow::anyhow!("Empty command for key: {}", key_combo));
+        }
+        let chain = CommandChain::parse(command)
+            .map_err(|e| anyhow::anyhow!("Invalid command for key {}: {}", key_combo, e))?;
+
+        for command in chain.commands() {
+            if let Command::Mode(mode) = command
+                && mode != DEFAULT_MODE
+                && !self.modes.contains_key(mode)
+            {
+                return Err(anyhow::anyhow!(
+                    "Unknown mode {:?} for key: {}",
+                    mode,
+                    key_combo
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Config {
     /// Loads configuration from file, creates default if not found
     pub fn load() -> Result<Self> {
@@ -209,18 +513,23 @@ impl Config {
 
         if config_path.exists() {
             info!("Loading config from: {:?}", config_path);
-            let content = std::fs::read_to_string(&config_path)?;
-            let config: Config = toml::from_str(&content)?;
-            config.validate()?;
-            Ok(config)
+            Self::load_from(&config_path)
         } else {
             info!("No config file found, using defaults");
             Ok(Self::default())
         }
     }
 
+    /// Reads and validates the config file at `path`
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
     /// Gets the config file path
-    fn config_path() -> Result<std::path::PathBuf> {
+    pub fn config_path() -> Result<std::path::PathBuf> {
         let config_dir =
             dirs::config_dir().ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?;
 
@@ -237,6 +546,54 @@ impl Config {
         &self.shortcuts
     }
 
+    /// Gets the programs launched with the window manager
+    pub fn autostart(&self) -> &AutostartConfig {
+        &self.autostart
+    }
+
+    /// Gets the commands run on window manager events
+    pub fn hooks(&self) -> &HooksConfig {
+        &self.hooks
+    }
+
+    /// Gets the named scratchpads by window class
+    pub fn scratchpads(&self) -> &HashMap<String, ScratchpadConfig> {
+        &self.scratchpads
+    }
+
+    /// Gets the window rules in the order they are applied
+    pub fn rules(&self) -> &[WindowRule] {
+        &self.rules
+    }
+
+    /// Gets the shell used to run launcher commands
+    pub fn shell(&self) -> &str {
+        &self.general.shell
+    }
+
+    /// Gets the focused border color used while `mode` is active
+    pub fn mode_focused_border_color(&self, mode: &str) -> u32 {
+        self.modes
+            .get(mode)
+            .and_then(|mode| mode.focused_border_color)
+            .unwrap_or(self.focused_border_color())
+    }
+
+    /// Gets how long a key sequence waits for its next key
+    pub fn sequence_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.general.sequence_timeout_ms)
+    }
+
+    /// Gets what takes focus after the focused window closes
+    pub fn focus_on_close(&self) -> FocusOnClose {
+        self.general.focus_on_close
+    }
+
+    /// Gets whether new windows take focus
+    pub fn focus_new_windows(&self) -> FocusNewWindows {
+        self.general.focus_new_windows
+    }
+
     /// Gets the border width for windows
     pub fn border_width(&self) -> u32 {
         self.layout.border_width
@@ -252,6 +609,11 @@ impl Config {
         self.layout.unfocused_border_color
     }
 
+    /// Gets the border color of windows that demand attention
+    pub fn urgent_border_color(&self) -> u32 {
+        self.layout.urgent_border_color
+    }
+
     /// Gets the gap between windows
     pub fn gap(&self) -> u32 {
         self.layout.gap
@@ -273,6 +635,13 @@ impl Config {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_example_config_loads() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        let config = Config::load_from(&path).unwrap();
+        assert!(!config.shortcuts.is_empty());
+    }
+
     #[test]
     fn test_default_config() {
         let config = Config::default();
@@ -298,6 +667,68 @@ mod tests {
         assert!(config.validate().is_err());
     }
 
+    #[test]
+    fn test_unknown_command_validation() {
+        let mut config = Config::default();
+        config
+            .shortcuts
+            .insert("Alt+n".to_string(), "focus_nxt".to_string());
+        assert!(config.validate().is_err());
+
+        config
+            .shortcuts
+            .insert("Alt+n".to_string(), "resize left 20".to_string());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_modes() {
+        let config: Config = toml::from_str(
+            r#"
+            [shortcuts]
+            "Super+r" = "mode resize"
+
+            [layout]
+            gap = 10
+            border_width = 5
+            focused_border_color = 0xFF0000
+            unfocused_border_color = 0x808080
+
+            [modes.resize]
+            focused_border_color = 0x00FF00
+
+            [modes.resize.shortcuts]
+            "h" = "resize left 20"
+            "Return" = "mode default"
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.mode_focused_border_color("resize"), 0x00FF00);
+        assert_eq!(config.mode_focused_border_color(DEFAULT_MODE), 0xFF0000);
+
+        // Entering an undefined mode is a config error
+        let mut broken = config.clone();
+        broken
+            .shortcuts
+            .insert("Super+m".to_string(), "mode move".to_string());
+        assert!(broken.validate().is_err());
+
+        // Mode bindings are validated like top-level ones
+        let mut broken = config.clone();
+        let resize = broken.modes.get_mut("resize").unwrap();
+        resize
+            .shortcuts
+            .insert("l".to_string(), "resize_rigth 20".to_string());
+        assert!(broken.validate().is_err());
+
+        let mut broken = config;
+        broken
+            .modes
+            .insert(DEFAULT_MODE.to_string(), ModeConfig::default());
+        assert!(broken.validate().is_err());
+    }
+
     #[test]
     fn test_gap_validation() {
         let mut config = Config::default();
@@ -394,6 +825,12 @@ mod tests {
         let config = Config {
             shortcuts: shortcuts.clone(),
             layout: LayoutConfig::default(),
+            general: GeneralConfig::default(),
+            modes: HashMap::new(),
+            rules: Vec::new(),
+            autostart: AutostartConfig::default(),
+            hooks: HooksConfig::default(),
+            scratchpads: HashMap::new(),
         };
 
         // Verify balance_tree keybinding is present
@@ -414,4 +851,153 @@ mod tests {
         // balance_tree keybinding should not be required
         assert!(config.shortcuts().get("Shift-Alt_L+0").is_none());
     }
+
+    #[test]
+    fn test_general_section_is_optional() {
+        let config: Config = toml::from_str(
+            r#"
+            [shortcuts]
+            "Alt+j" = "focus_next"
+
+            [layout]
+            gap = 10
+            border_width = 5
+            focused_border_color = 0xFF0000
+            unfocused_border_color = 0x808080
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.shell(), "/bin/sh");
+        assert_eq!(config.sequence_timeout().as_millis(), 1000);
+        assert_eq!(config.focus_on_close(), FocusOnClose::Mru);
+        assert_eq!(config.focus_new_windows(), FocusNewWindows::Always);
+        assert_eq!(config.urgent_border_color(), 0xFFA500);
+
+        let mut config = config;
+        config.general.sequence_timeout_ms = 0;
+        assert!(config.validate().is_err());
+
+        config.general.sequence_timeout_ms = 500;
+        config.general.shell = " ".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_focus_on_close() {
+        let general: GeneralConfig = toml::from_str(r#"focus_on_close = "sibling""#).unwrap();
+        assert_eq!(general.focus_on_close, FocusOnClose::Sibling);
+        let general: GeneralConfig = toml::from_str(r#"focus_on_close = "none""#).unwrap();
+        assert_eq!(general.focus_on_close, FocusOnClose::None);
+        assert!(toml::from_str::<GeneralConfig>(r#"focus_on_close = "last""#).is_err());
+    }
+
+    #[test]
+    fn test_focus_new_windows() {
+        let general: GeneralConfig =
+            toml::from_str(r#"focus_new_windows = "if_same_workspace""#).unwrap();
+        assert_eq!(general.focus_new_windows, FocusNewWindows::IfSameWorkspace);
+        assert!(toml::from_str::<GeneralConfig>(r#"focus_new_windows = "sometimes""#).is_err());
+
+        assert!(FocusNewWindows::Always.allows(false));
+        assert!(!FocusNewWindows::Never.allows(true));
+        assert!(FocusNewWindows::IfSameWorkspace.allows(true));
+        assert!(!FocusNewWindows::IfSameWorkspace.allows(false));
+    }
+
+    #[test]
+    fn test_rules_are_validated() {
+        let base = r#"
+            [shortcuts]
+            "Alt+j" = "focus_next"
+
+            [layout]
+            gap = 10
+            border_width = 5
+            focused_border_color = 0xFF0000
+            unfocused_border_color = 0x808080
+        "#;
+        let config: Config = toml::from_str(base).unwrap();
+        assert!(config.rules().is_empty());
+
+        let config: Config = toml::from_str(&format!(
+            "{base}\n[[rules]]\nclass = \"mpv\"\nfloating = true\n"
+        ))
+        .unwrap();
+        assert_eq!(config.rules().len(), 1);
+        assert!(config.validate().is_ok());
+
+        let config: Config =
+            toml::from_str(&format!("{base}\n[[rules]]\nclass = \"mpv\"\n")).unwrap();
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.contains("window rule 1"), "{error}");
+    }
+
+    #[test]
+    fn test_autostart() {
+        let autostart: AutostartConfig = toml::from_str(
+            r#"
+            exec = ["nm-applet", "dunst"]
+            exec_always = ["~/.fehbg"]
+
+            [workspaces]
+            mail = ["thunderbird"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(autostart.exec, ["nm-applet", "dunst"]);
+        assert_eq!(autostart.workspaces["mail"], ["thunderbird"]);
+        assert!(autostart.validate().is_ok());
+
+        let empty: AutostartConfig = toml::from_str(r#"exec_always = [" "]"#).unwrap();
+        assert!(empty.validate().is_err());
+        let index: AutostartConfig = toml::from_str("workspaces.2 = [\"xterm\"]").unwrap();
+        assert!(index.validate().is_err());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let hooks: HooksConfig = toml::from_str(
+            r#"
+            on_workspace_switch = "pkill -STOP mpv"
+            on_shutdown = "notify-send bye"
+            "#,
+        )
+        .unwrap();
+        assert!(hooks.validate().is_ok());
+        let switched = WmEvent::WorkspaceSwitched { from: 0, to: 1 };
+        assert_eq!(hooks.command(&switched), Some("pkill -STOP mpv"));
+        assert_eq!(hooks.command(&WmEvent::Shutdown), Some("notify-send bye"));
+        assert_eq!(hooks.command(&WmEvent::FocusChanged { window: None }), None);
+        assert_eq!(hooks.command(&WmEvent::ConfigReloaded), None);
+
+        let empty: HooksConfig = toml::from_str("on_fullscreen = ' '").unwrap();
+        assert!(empty.validate().is_err());
+        assert!(toml::from_str::<HooksConfig>("on_window_mapped = 'x'").is_err());
+    }
+
+    #[test]
+    fn test_scratchpads() {
+        let base = r#"
+            [shortcuts]
+            [layout]
+            gap = 10
+            border_width = 5
+            focused_border_color = 0xFF0000
+            unfocused_border_color = 0x808080
+        "#;
+        let config: Config = toml::from_str(&format!(
+            "{base}\n[scratchpads.scratch-term]\ncommand = \"alacritty --class scratch-term\"\ngeometry = \"1200x700\"\n"
+        ))
+        .unwrap();
+        let scratchpad = &config.scratchpads()["scratch-term"];
+        assert_eq!(
+            scratchpad.geometry,
+            Some(Geometry::parse("1200x700").unwrap())
+        );
+        assert!(config.validate().is_ok());
+
+        let config: Config =
+            toml::from_str(&format!("{base}\n[scratchpads.notes]\ncommand = \" \"\n")).unwrap();
+        assert!(config.validate().is_err());
+    }
 }
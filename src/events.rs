@@ -0,0 +1,332 @@
+//! Typed window manager events, logged and streamed to IPC subscribers
+
+use serde::Serialize;
+use std::fmt;
+use tracing::info;
+use x11rb::protocol::xproto::Window;
+
+/// A state change worth telling the outside world about
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum WmEvent {
+    WindowMapped {
+        window: Window,
+        workspace: usize,
+    },
+    WindowUnmapped {
+        window: Window,
+    },
+    WindowDestroyed {
+        window: Window,
+    },
+    FocusChanged {
+        window: Option<Window>,
+    },
+    WorkspaceCreated {
+        index: usize,
+    },
+    WorkspaceDeleted {
+        index: usize,
+    },
+    WorkspaceSwitched {
+        from: usize,
+        to: usize,
+    },
+    LayoutChanged {
+        workspace: usize,
+        change: LayoutChange,
+    },
+    FullscreenToggled {
+        window: Window,
+        enabled: bool,
+    },
+    ZoomToggled {
+        window: Window,
+        enabled: bool,
+    },
+    StickyToggled {
+        window: Window,
+        enabled: bool,
+    },
+    ModeChanged {
+        mode: String,
+    },
+    UrgencyChanged {
+        window: Window,
+        workspace: usize,
+        urgent: bool,
+    },
+    ConfigReloaded,
+    Shutdown,
+}
+
+/// What kind of layout change happened
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LayoutChange {
+    Swap,
+    Rotate,
+    Balance,
+    Resize,
+}
+
+impl WmEvent {
+    /// All event kind names accepted by `subscribe`
+    pub const KINDS: &'static [&'static str] = &[
+        "window_mapped",
+        "window_unmapped",
+        "window_destroyed",
+        "focus_changed",
+        "workspace_created",
+        "workspace_deleted",
+        "workspace_switched",
+        "layout_changed",
+        "fullscreen_toggled",
+        "zoom_toggled",
+        "sticky_toggled",
+        "mode_changed",
+        "urgency_changed",
+        "config_reloaded",
+        "shutdown",
+    ];
+
+    /// Returns the event kind name (same as the JSON "event" field)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WmEvent::WindowMapped { .. } => "window_mapped",
+            WmEvent::WindowUnmapped { .. } => "window_unmapped",
+            WmEvent::WindowDestroyed { .. } => "window_destroyed",
+            WmEvent::FocusChanged { .. } => "focus_changed",
+            WmEvent::WorkspaceCreated { .. } => "workspace_created",
+            WmEvent::WorkspaceDeleted { .. } => "workspace_deleted",
+            WmEvent::WorkspaceSwitched { .. } => "workspace_switched",
+            WmEvent::LayoutChanged { .. } => "layout_changed",
+            WmEvent::FullscreenToggled { .. } => "fullscreen_toggled",
+            WmEvent::ZoomToggled { .. } => "zoom_toggled",
+            WmEvent::StickyToggled { .. } => "sticky_toggled",
+            WmEvent::ModeChanged { .. } => "mode_changed",
+            WmEvent::UrgencyChanged { .. } => "urgency_changed",
+            WmEvent::ConfigReloaded => "config_reloaded",
+            WmEvent::Shutdown => "shutdown",
+        }
+    }
+
+    /// Returns the window the event is about, if any
+    pub fn window(&self) -> Option<Window> {
+        match self {
+            WmEvent::WindowMapped { window, .. }
+            | WmEvent::WindowUnmapped { window }
+            | WmEvent::WindowDestroyed { window }
+            | WmEvent::FullscreenToggled { window, .. }
+            | WmEvent::ZoomToggled { window, .. }
+            | WmEvent::StickyToggled { window, .. }
+            | WmEvent::UrgencyChanged { window, .. } => Some(*window),
+            WmEvent::FocusChanged { window } => *window,
+            _ => None,
+        }
+    }
+
+    /// Returns the workspace the event happened on, if it names one
+    pub fn workspace(&self) -> Option<usize> {
+        match self {
+            WmEvent::WindowMapped { workspace, .. }
+            | WmEvent::LayoutChanged { workspace, .. }
+            | WmEvent::UrgencyChanged { workspace, .. } => Some(*workspace),
+            WmEvent::WorkspaceCreated { index } | WmEvent::WorkspaceDeleted { index } => {
+                Some(*index)
+            }
+            WmEvent::WorkspaceSwitched { to, .. } => Some(*to),
+            _ => None,
+        }
+    }
+
+    /// Event-specific variables for hooks; window and workspace details are added by the caller
+    pub fn hook_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![("RUSTILE_EVENT", self.kind().to_string())];
+        match self {
+            WmEvent::WorkspaceSwitched { from, .. } => {
+                env.push(("RUSTILE_PREVIOUS_WORKSPACE", from.to_string()));
+            }
+            WmEvent::LayoutChanged { change, .. } => {
+                let change = serde_json::to_value(change).unwrap_or_default();
+                env.push((
+                    "RUSTILE_LAYOUT_CHANGE",
+                    change.as_str().unwrap_or_default().into(),
+                ));
+            }
+            WmEvent::FullscreenToggled { enabled, .. } => {
+                env.push(("RUSTILE_FULLSCREEN", u8::from(*enabled).to_string()));
+            }
+            _ => {}
+        }
+        env
+    }
+}
+
+impl fmt::Display for WmEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WmEvent::WindowMapped { window, workspace } => {
+                write!(f, "Mapped window {window:?} on workspace {workspace}")
+            }
+            WmEvent::WindowUnmapped { window } => {
+                write!(
+                    f,
+                    "Window {window:?} closed by user, removed from management"
+                )
+            }
+            WmEvent::WindowDestroyed { window } => write!(f, "Window destroyed: {window:?}"),
+            WmEvent::FocusChanged { window } => write!(f, "Focus changed to {window:?}"),
+            WmEvent::WorkspaceCreated { index } => write!(f, "Created workspace {index}"),
+            WmEvent::WorkspaceDeleted { index } => write!(f, "Deleted workspace {index}"),
+            WmEvent::WorkspaceSwitched { from, to } => {
+                write!(f, "Switched workspace: {from} -> {to}")
+            }
+            WmEvent::LayoutChanged { workspace, change } => {
+                write!(f, "Layout changed on workspace {workspace}: {change:?}")
+            }
+            WmEvent::FullscreenToggled { window, enabled } => {
+                let state = if *enabled { "Entered" } else { "Exited" };
+                write!(f, "{state} fullscreen mode for window {window:?}")
+            }
+            WmEvent::ZoomToggled { window, enabled } => {
+                let state = if *enabled { "Zoomed" } else { "Unzoomed" };
+                write!(f, "{state} window: {window:?}")
+            }
+            WmEvent::StickyToggled { window, enabled } => {
+                let state = if *enabled { "Stuck" } else { "Unstuck" };
+                write!(f, "{state} window {window:?} on all workspaces")
+            }
+            WmEvent::ModeChanged { mode } => write!(f, "Switched to {mode} mode"),
+            WmEvent::UrgencyChanged {
+                window,
+                workspace,
+                urgent,
+            } => {
+                let state = if *urgent {
+                    "demands"
+                } else {
+                    "no longer demands"
+                };
+                write!(
+                    f,
+                    "Window {window:?} on workspace {workspace} {state} attention"
+                )
+            }
+            WmEvent::ConfigReloaded => write!(f, "Configuration reloaded"),
+            WmEvent::Shutdown => write!(f, "Shutting down"),
+        }
+    }
+}
+
+/// Collects emitted events until the event loop dispatches them
+#[derive(Debug, Default)]
+pub struct EventBus {
+    pending: Vec<WmEvent>,
+}
+
+impl EventBus {
+    /// Creates an empty event bus
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Logs the event and queues it for subscribers
+    pub fn emit(&mut self, event: WmEvent) {
+        info!("{}", event);
+        self.pending.push(event);
+    }
+
+    /// Takes all queued events
+    pub fn drain(&mut self) -> Vec<WmEvent> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_json_format() {
+        let event = WmEvent::WorkspaceSwitched { from: 0, to: 2 };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(json, r#"{"event":"workspace_switched","from":0,"to":2}"#);
+
+        let event = WmEvent::LayoutChanged {
+            workspace: 1,
+            change: LayoutChange::Rotate,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["change"], "rotate");
+    }
+
+    #[test]
+    fn test_kind_matches_json_tag() {
+        let events = [
+            WmEvent::FocusChanged { window: None },
+            WmEvent::ZoomToggled {
+                window: 1,
+                enabled: true,
+            },
+            WmEvent::StickyToggled {
+                window: 1,
+                enabled: false,
+            },
+            WmEvent::ModeChanged {
+                mode: "resize".to_string(),
+            },
+            WmEvent::UrgencyChanged {
+                window: 1,
+                workspace: 0,
+                urgent: true,
+            },
+            WmEvent::Shutdown,
+        ];
+        for event in events {
+            let json = serde_json::to_value(&event).unwrap();
+            assert_eq!(json["event"], event.kind());
+            assert!(WmEvent::KINDS.contains(&event.kind()));
+        }
+    }
+
+    #[test]
+    fn test_event_bus_drain() {
+        let mut bus = EventBus::new();
+        bus.emit(WmEvent::WorkspaceCreated { index: 1 });
+        bus.emit(WmEvent::Shutdown);
+
+        assert_eq!(bus.drain().len(), 2);
+        assert!(bus.drain().is_empty());
+    }
+
+    #[test]
+    fn test_hook_env() {
+        let event = WmEvent::WorkspaceSwitched { from: 0, to: 2 };
+        assert_eq!(event.workspace(), Some(2));
+        assert_eq!(event.window(), None);
+        assert_eq!(
+            event.hook_env(),
+            [
+                ("RUSTILE_EVENT", "workspace_switched".to_string()),
+                ("RUSTILE_PREVIOUS_WORKSPACE", "0".to_string()),
+            ]
+        );
+
+        let event = WmEvent::LayoutChanged {
+            workspace: 1,
+            change: LayoutChange::Balance,
+        };
+        assert_eq!(
+            event.hook_env()[1],
+            ("RUSTILE_LAYOUT_CHANGE", "balance".to_string())
+        );
+
+        let event = WmEvent::FullscreenToggled {
+            window: 7,
+            enabled: true,
+        };
+        assert_eq!(event.window(), Some(7));
+        assert_eq!(event.hook_env()[1], ("RUSTILE_FULLSCREEN", "1".to_string()));
+    }
+}
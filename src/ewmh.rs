@@ -0,0 +1,230 @@
+//! EWMH (_NET_*) and ICCCM window properties read from and written to clients
+
+use anyhow::Result;
+use x11rb::CURRENT_TIME;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt, PropMode, Timestamp, Window};
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::rules::WindowInfo;
+
+/// `_NET_WM_DESKTOP` value of windows shown on every desktop
+pub const ALL_DESKTOPS: u32 = 0xFFFF_FFFF;
+
+x11rb::atom_manager! {
+    /// Atoms the window manager reads and writes, interned once at start-up
+    pub Atoms: AtomsCookie {
+        UTF8_STRING,
+        WM_WINDOW_ROLE,
+        _NET_WM_NAME,
+        _NET_WM_WINDOW_TYPE,
+        _NET_WM_USER_TIME,
+        _NET_WM_USER_TIME_WINDOW,
+        _NET_WM_PID,
+        _NET_WM_DESKTOP,
+        _NET_SUPPORTED,
+        _NET_WM_STATE,
+        _NET_WM_STATE_STICKY,
+        _NET_WM_STATE_DEMANDS_ATTENTION,
+        _RUSTILE_MODE,
+    }
+}
+
+/// Reads a single CARDINAL or WINDOW property value
+fn read_u32(conn: &impl Connection, window: Window, property: Atom, kind: AtomEnum) -> Option<u32> {
+    conn.get_property(false, window, property, kind, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()
+}
+
+/// Reads a text property of any type, None if it is not set
+fn read_text(conn: &impl Connection, window: Window, property: Atom) -> Result<Option<String>> {
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::ANY, 0, 1024)?
+        .reply()?;
+    if reply.value.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()))
+}
+
+/// Reads the class, title, role and type that window rules match on
+pub fn window_info(conn: &impl Connection, atoms: &Atoms, window: Window) -> Result<WindowInfo> {
+    // WM_CLASS holds the instance and the class, each terminated by a NUL
+    let wm_class = read_text(conn, window, AtomEnum::WM_CLASS.into())?.unwrap_or_default();
+    let mut parts = wm_class.split('\0');
+    let instance = parts.next().unwrap_or_default().to_string();
+    let class = parts.next().unwrap_or_default().to_string();
+
+    let title = match read_text(conn, window, atoms._NET_WM_NAME)? {
+        Some(title) => title,
+        None => read_text(conn, window, AtomEnum::WM_NAME.into())?.unwrap_or_default(),
+    };
+    let role = read_text(conn, window, atoms.WM_WINDOW_ROLE)?.unwrap_or_default();
+
+    let window_type = match read_u32(conn, window, atoms._NET_WM_WINDOW_TYPE, AtomEnum::ATOM) {
+        Some(type_atom) => {
+            let name = conn.get_atom_name(type_atom)?.reply()?.name;
+            String::from_utf8_lossy(&name)
+                .trim_start_matches("_NET_WM_WINDOW_TYPE_")
+                .to_lowercase()
+        }
+        None => "normal".to_string(),
+    };
+
+    Ok(WindowInfo {
+        class,
+        instance,
+        title,
+        role,
+        window_type,
+    })
+}
+
+/// Reads `_NET_WM_USER_TIME`, following `_NET_WM_USER_TIME_WINDOW` if the client uses one
+pub fn user_time(conn: &impl Connection, atoms: &Atoms, window: Window) -> Option<Timestamp> {
+    let user_time = atoms._NET_WM_USER_TIME;
+    let time_window = read_u32(
+        conn,
+        window,
+        atoms._NET_WM_USER_TIME_WINDOW,
+        AtomEnum::WINDOW,
+    )
+    .unwrap_or(window);
+    read_u32(conn, time_window, user_time, AtomEnum::CARDINAL)
+        .or_else(|| read_u32(conn, window, user_time, AtomEnum::CARDINAL))
+}
+
+/// Reads `_NET_WM_PID`, the process id the client says owns the window
+pub fn window_pid(conn: &impl Connection, atoms: &Atoms, window: Window) -> Option<u32> {
+    read_u32(conn, window, atoms._NET_WM_PID, AtomEnum::CARDINAL)
+}
+
+/// Lists the hints we handle in `_NET_SUPPORTED` on the root window
+pub fn set_supported(conn: &impl Connection, atoms: &Atoms, root: Window) -> Result<()> {
+    let supported = [
+        atoms._NET_WM_STATE,
+        atoms._NET_WM_STATE_STICKY,
+        atoms._NET_WM_STATE_DEMANDS_ATTENTION,
+        atoms._NET_WM_USER_TIME,
+    ];
+    conn.change_property32(
+        PropMode::REPLACE,
+        root,
+        atoms._NET_SUPPORTED,
+        AtomEnum::ATOM,
+        &supported,
+    )?;
+    Ok(())
+}
+
+/// Checks if a state such as `_NET_WM_STATE_STICKY` is set in a window's `_NET_WM_STATE`
+pub fn has_wm_state(conn: &impl Connection, atoms: &Atoms, window: Window, state: Atom) -> bool {
+    conn.get_property(false, window, atoms._NET_WM_STATE, AtomEnum::ATOM, 0, 1024)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .is_some_and(|reply| reply.value32().into_iter().flatten().any(|s| s == state))
+}
+
+/// Adds or removes a state such as `_NET_WM_STATE_DEMANDS_ATTENTION` in `_NET_WM_STATE`
+pub fn set_wm_state(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    window: Window,
+    state: Atom,
+    enabled: bool,
+) -> Result<()> {
+    let property = atoms._NET_WM_STATE;
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::ATOM, 0, 1024)?
+        .reply()?;
+    let mut states: Vec<Atom> = reply.value32().into_iter().flatten().collect();
+    if states.contains(&state) == enabled {
+        return Ok(());
+    }
+    if enabled {
+        states.push(state);
+    } else {
+        states.retain(|&s| s != state);
+    }
+    conn.change_property32(PropMode::REPLACE, window, property, AtomEnum::ATOM, &states)?;
+    Ok(())
+}
+
+/// Sets `_NET_WM_DESKTOP`, or removes it when there is no desktop to report
+pub fn set_desktop(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    window: Window,
+    desktop: Option<u32>,
+) -> Result<()> {
+    let property = atoms._NET_WM_DESKTOP;
+    match desktop {
+        Some(desktop) => {
+            conn.change_property32(
+                PropMode::REPLACE,
+                window,
+                property,
+                AtomEnum::CARDINAL,
+                &[desktop],
+            )?;
+        }
+        None => {
+            conn.delete_property(window, property)?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the action of a `_NET_WM_STATE` client message (0 remove, 1 add, 2 toggle)
+/// to a state's current value; None for an unknown action
+pub fn state_action(action: u32, current: bool) -> Option<bool> {
+    match action {
+        0 => Some(false),
+        1 => Some(true),
+        2 => Some(!current),
+        _ => None,
+    }
+}
+
+/// Returns true if `time` is before `reference`, allowing for the 32-bit server clock wrapping
+/// A zero timestamp means "never interacted with" and counts as older than anything,
+/// while a zero reference (CURRENT_TIME, nothing recorded yet) has nothing older than it.
+pub fn is_older(time: Timestamp, reference: Timestamp) -> bool {
+    if reference == CURRENT_TIME {
+        return false;
+    }
+    time == 0 || (reference.wrapping_sub(time) as i32) > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_older() {
+        assert!(is_older(100, 200));
+        assert!(!is_older(200, 100));
+        assert!(!is_older(200, 200));
+        assert!(is_older(0, 200));
+        // Just before and after the server clock wraps around
+        assert!(is_older(u32::MAX - 10, 5));
+        assert!(!is_older(5, u32::MAX - 10));
+        // No reference yet, e.g. before focus first changes: even late server times are not older
+        assert!(!is_older(100, CURRENT_TIME));
+        assert!(!is_older(u32::MAX / 2 + 100, CURRENT_TIME));
+        assert!(!is_older(0, CURRENT_TIME));
+    }
+
+    #[test]
+    fn test_state_action() {
+        assert_eq!(state_action(0, true), Some(false));
+        assert_eq!(state_action(1, false), Some(true));
+        assert_eq!(state_action(2, true), Some(false));
+        assert_eq!(state_action(2, false), Some(true));
+        assert_eq!(state_action(3, false), None);
+    }
+}
@@ -0,0 +1,631 @@
+//! Unix socket IPC for controlling rustile from scripts (see rustile-msg)
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{ErrorKind, Read, Write};
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+use x11rb::protocol::xproto::Window;
+
+use crate::bsp::{BspNode, SplitDirection};
+use crate::events::WmEvent;
+use crate::workspace::Workspace;
+use crate::workspace_renderer::WindowGeometry;
+
+pub use crate::socket_path::socket_path;
+
+/// How long a client may take to send its request line before it is dropped
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a reply may take to be written to a client that is not reading it
+const REPLY_WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Longest request line accepted
+const MAX_REQUEST_LEN: usize = 64 * 1024;
+
+/// Unsent event bytes a subscriber may fall behind by before it is dropped
+const MAX_SUBSCRIBER_BACKLOG: usize = 1024 * 1024;
+
+/// How long a dropped subscriber gets to read the rest of its last event line
+const SUBSCRIBER_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Listening socket that accepts one command line per connection
+pub struct IpcServer {
+    listener: UnixListener,
+    path: PathBuf,
+    /// Connections whose request line has not fully arrived yet
+    clients: Vec<Client>,
+    subscribers: Vec<Subscriber>,
+}
+
+/// A connection still sending its request line, read without blocking
+struct Client {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    deadline: Instant,
+}
+
+impl Client {
+    /// Reads what has arrived; returns the request line once complete, or None to wait
+    /// for more. Errors mean the connection is closed, overlong or broken.
+    fn read_request(&mut self) -> Result<Option<String>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                // The client closed its end: whatever it sent is the request
+                Ok(0) if self.buffer.is_empty() => {
+                    return Err(anyhow::anyhow!("connection closed without a request"));
+                }
+                Ok(0) => return Ok(Some(self.take_line(self.buffer.len()))),
+                Ok(read) => self.buffer.extend_from_slice(&chunk[..read]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+            if let Some(end) = self.buffer.iter().position(|&b| b == b'\n') {
+                return Ok(Some(self.take_line(end)));
+            }
+            if self.buffer.len() > MAX_REQUEST_LEN {
+                return Err(anyhow::anyhow!(
+                    "request longer than {} bytes",
+                    MAX_REQUEST_LEN
+                ));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Takes the first `len` bytes of the buffer as the trimmed request line
+    fn take_line(&mut self, len: usize) -> String {
+        String::from_utf8_lossy(&self.buffer[..len])
+            .trim()
+            .to_string()
+    }
+}
+
+/// A connection kept open to receive newline-delimited JSON events
+struct Subscriber {
+    stream: UnixStream,
+    /// Event kinds to send; empty means all
+    kinds: HashSet<String>,
+    /// Event lines not yet taken by the socket, written as it drains
+    outgoing: Vec<u8>,
+    /// Set once the subscriber fell too far behind: it is closed after its current line
+    closing: Option<Instant>,
+}
+
+impl Subscriber {
+    /// Queues an event line; a subscriber too far behind only gets the line it is in
+    fn queue(&mut self, line: &str) {
+        if self.closing.is_some() {
+            return;
+        }
+        if self.outgoing.len() + line.len() > MAX_SUBSCRIBER_BACKLOG {
+            info!("Dropping IPC subscriber that stopped reading events");
+            // Finish the first line, which may be partly sent, so the stream ends on a line boundary
+            let end = self
+                .outgoing
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(0, |i| i + 1);
+            self.outgoing.truncate(end);
+            self.closing = Some(Instant::now() + SUBSCRIBER_CLOSE_TIMEOUT);
+            return;
+        }
+        self.outgoing.extend_from_slice(line.as_bytes());
+    }
+
+    /// Writes as much queued output as the socket takes without blocking
+    /// Returns false once the subscriber should be dropped.
+    fn flush(&mut self, now: Instant) -> bool {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return false,
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    info!("Dropping IPC subscriber: {}", e);
+                    return false;
+                }
+            }
+        }
+        match self.closing {
+            Some(deadline) => !self.outgoing.is_empty() && deadline > now,
+            None => true,
+        }
+    }
+}
+
+impl IpcServer {
+    /// Binds the IPC socket, replacing a stale socket file left by a previous run
+    pub fn bind(path: &Path) -> Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(anyhow::anyhow!(
+                    "IPC socket {:?} is already in use by another rustile",
+                    path
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        info!("IPC socket listening on {:?}", path);
+
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+            clients: Vec::new(),
+            subscribers: Vec::new(),
+        })
+    }
+
+    /// Accepts pending connections and returns every request line that has fully arrived
+    /// Never blocks: partial lines stay buffered until the client sends the rest
+    pub fn accept_requests(&mut self) -> Vec<IpcRequest> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        error!("Failed to set up IPC connection: {}", e);
+                        continue;
+                    }
+                    self.clients.push(Client {
+                        stream,
+                        buffer: Vec::new(),
+                        deadline: Instant::now() + REQUEST_READ_TIMEOUT,
+                    });
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("Failed to accept IPC connection: {}", e);
+                    break;
+                }
+            }
+        }
+
+        let mut requests = Vec::new();
+        let now = Instant::now();
+        let mut clients = std::mem::take(&mut self.clients);
+        for mut client in clients.drain(..) {
+            match client.read_request() {
+                Ok(Some(message)) => requests.push(IpcRequest {
+                    stream: client.stream,
+                    message,
+                }),
+                Ok(None) if client.deadline <= now => {
+                    error!("Dropping IPC client that sent no request in time");
+                }
+                Ok(None) => self.clients.push(client),
+                Err(e) => error!("Failed to read IPC request: {}", e),
+            }
+        }
+
+        requests
+    }
+
+    /// Connections waiting for the rest of their request line, to poll alongside the listener
+    pub fn client_fds(&self) -> impl Iterator<Item = BorrowedFd<'_>> {
+        self.clients.iter().map(|client| client.stream.as_fd())
+    }
+
+    /// Subscribers with queued events, to poll for writability alongside the listener
+    pub fn pending_output_fds(&self) -> impl Iterator<Item = BorrowedFd<'_>> {
+        self.subscribers
+            .iter()
+            .filter(|subscriber| !subscriber.outgoing.is_empty())
+            .map(|subscriber| subscriber.stream.as_fd())
+    }
+
+    /// When the oldest waiting connection or closing subscriber times out,
+    /// so the event loop wakes up to drop it
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let closing = self.subscribers.iter().filter_map(|s| s.closing);
+        self.clients
+            .iter()
+            .map(|client| client.deadline)
+            .chain(closing)
+            .min()
+    }
+
+    /// Keeps the request's connection open as an event subscriber
+    pub fn subscribe(&mut self, request: IpcRequest, kinds: HashSet<String>) {
+        // Never block the event loop on a slow subscriber
+        if let Err(e) = request.stream.set_nonblocking(true) {
+            error!("Failed to set up IPC subscriber: {}", e);
+            return;
+        }
+        info!(
+            "IPC subscriber added ({} total)",
+            self.subscribers.len() + 1
+        );
+        self.subscribers.push(Subscriber {
+            stream: request.stream,
+            kinds,
+            outgoing: Vec::new(),
+            closing: None,
+        });
+    }
+
+    /// Queues events for interested subscribers and sends what their sockets take
+    /// The rest goes out from `flush_subscribers` as they read
+    pub fn broadcast(&mut self, events: &[WmEvent]) {
+        if self.subscribers.is_empty() || events.is_empty() {
+            return;
+        }
+
+        for event in events {
+            let line = match serde_json::to_string(event) {
+                Ok(json) => json + "\n",
+                Err(e) => {
+                    error!("Failed to serialize event {:?}: {}", event, e);
+                    continue;
+                }
+            };
+            for subscriber in &mut self.subscribers {
+                if subscriber.kinds.is_empty() || subscriber.kinds.contains(event.kind()) {
+                    subscriber.queue(&line);
+                }
+            }
+        }
+        self.flush_subscribers();
+    }
+
+    /// Writes queued events without blocking, dropping disconnected or stalled subscribers
+    pub fn flush_subscribers(&mut self) {
+        let now = Instant::now();
+        self.subscribers
+            .retain_mut(|subscriber| subscriber.flush(now));
+    }
+}
+
+/// Parses "subscribe [kind...]", returning None for anything else
+pub fn parse_subscribe(message: &str) -> Option<Result<HashSet<String>>> {
+    let mut parts = message.split_whitespace();
+    if parts.next() != Some("subscribe") {
+        return None;
+    }
+
+    let mut kinds = HashSet::new();
+    for kind in parts {
+        if !WmEvent::KINDS.contains(&kind) {
+            return Some(Err(anyhow::anyhow!(
+                "Unknown event kind: {} (expected one of: {})",
+                kind,
+                WmEvent::KINDS.join(", ")
+            )));
+        }
+        kinds.insert(kind.to_string());
+    }
+    Some(Ok(kinds))
+}
+
+impl AsFd for IpcServer {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.listener.as_fd()
+    }
+}
+
+impl Drop for IpcServer {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A single client request: one command line, answered with one response
+pub struct IpcRequest {
+    stream: UnixStream,
+    pub message: String,
+}
+
+impl IpcRequest {
+    /// Sends the response and closes the connection
+    /// Large replies may not fit the socket buffer, so this waits (bounded) for the client to read
+    pub fn reply(mut self, response: &str) {
+        let blocking = self
+            .stream
+            .set_nonblocking(false)
+            .and_then(|()| self.stream.set_write_timeout(Some(REPLY_WRITE_TIMEOUT)));
+        if let Err(e) = blocking.and_then(|()| writeln!(self.stream, "{response}")) {
+            error!("Failed to send IPC response: {}", e);
+        }
+    }
+}
+
+// === State Queries ===
+
+/// State queries answered with a single line of JSON
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Query {
+    Workspaces,
+    Tree,
+    Config,
+    Mode,
+}
+
+impl Query {
+    /// Parses a query name, returning None for anything that is a command instead
+    pub fn parse(message: &str) -> Option<Self> {
+        match message {
+            "get_workspaces" => Some(Self::Workspaces),
+            "get_tree" => Some(Self::Tree),
+            "get_config" => Some(Self::Config),
+            "get_mode" => Some(Self::Mode),
+            _ => None,
+        }
+    }
+}
+
+/// Reply to get_mode
+#[derive(Debug, Serialize)]
+pub struct ModeReply<'a> {
+    pub mode: &'a str,
+}
+
+/// Reply to get_workspaces
+#[derive(Debug, Serialize)]
+pub struct WorkspacesReply {
+    pub current: usize,
+    pub workspaces: Vec<WorkspaceInfo>,
+}
+
+/// Summary of a single workspace
+#[derive(Debug, Serialize)]
+pub struct WorkspaceInfo {
+    pub index: usize,
+    pub name: Option<String>,
+    pub visible: bool,
+    pub windows: Vec<Window>,
+    /// Windows from most to least recently focused
+    pub mru: Vec<Window>,
+    pub focused_window: Option<Window>,
+    pub fullscreen_window: Option<Window>,
+    pub zoomed_window: Option<Window>,
+    /// Windows demanding attention
+    pub urgent: Vec<Window>,
+    /// Windows floating above the layout, bottom to top
+    pub floating: Vec<Window>,
+}
+
+impl WorkspaceInfo {
+    /// Builds the summary for the workspace at the given index
+    pub fn new(index: usize, workspace: &Workspace, visible: bool) -> Self {
+        Self {
+            index,
+            name: workspace.name().map(str::to_string),
+            visible,
+            windows: workspace.get_all_windows(),
+            mru: workspace.mru_windows(),
+            focused_window: workspace.focused_window(),
+            fullscreen_window: workspace.fullscreen_window(),
+            zoomed_window: workspace.zoomed_window(),
+            urgent: workspace.urgent_windows().to_vec(),
+            floating: workspace
+                .floating_windows()
+                .iter()
+                .map(|g| g.window)
+                .collect(),
+        }
+    }
+}
+
+/// Layout tree of a single workspace, as returned by get_tree
+#[derive(Debug, Serialize)]
+pub struct WorkspaceTree {
+    pub index: usize,
+    pub root: Option<TreeNode>,
+    /// Windows floating above the tree, bottom to top
+    pub floating: Vec<WindowGeometry>,
+}
+
+/// BSP node with the computed geometry of each leaf
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TreeNode {
+    Split {
+        direction: SplitDirection,
+        ratio: f32,
+        left: Box<TreeNode>,
+        right: Box<TreeNode>,
+    },
+    Leaf {
+        window: Window,
+        geometry: Option<WindowGeometry>,
+    },
+}
+
+impl TreeNode {
+    /// Converts a BSP node, looking up each leaf's geometry
+    pub fn new(node: &BspNode, geometries: &[WindowGeometry]) -> Self {
+        match node {
+            BspNode::Split {
+                direction,
+                ratio,
+                left,
+                right,
+            } => Self::Split {
+                direction: *direction,
+                ratio: *ratio,
+                left: Box::new(Self::new(left, geometries)),
+                right: Box::new(Self::new(right, geometries)),
+            },
+            BspNode::Leaf(window) => Self::Leaf {
+                window: *window,
+                geometry: geometries.iter().find(|g| g.window == *window).copied(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    #[test]
+    fn test_request_roundtrip() {
+        let path = std::env::temp_dir().join(format!("rustile-test-{}.sock", std::process::id()));
+        let mut server = IpcServer::bind(&path).unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        writeln!(client, "  focus_next  ").unwrap();
+
+        let mut requests = server.accept_requests();
+        assert_eq!(requests.len(), 1);
+        let request = requests.remove(0);
+        assert_eq!(request.message, "focus_next");
+        request.reply("ok");
+
+        let mut response = String::new();
+        BufReader::new(&client).read_line(&mut response).unwrap();
+        assert_eq!(response, "ok\n");
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_slow_clients_do_not_block() {
+        let path = std::env::temp_dir().join(format!("rustile-slow-{}.sock", std::process::id()));
+        let mut server = IpcServer::bind(&path).unwrap();
+
+        let _silent = UnixStream::connect(&path).unwrap();
+        let mut slow = UnixStream::connect(&path).unwrap();
+        write!(slow, "switch_work").unwrap();
+        let started = Instant::now();
+        assert!(server.accept_requests().is_empty());
+        assert!(started.elapsed() < REQUEST_READ_TIMEOUT / 2);
+        assert_eq!(server.client_fds().count(), 2);
+        assert!(server.next_deadline().is_some());
+
+        // The rest of the line completes the buffered request
+        writeln!(slow, "space 1").unwrap();
+        let requests = server.accept_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].message, "switch_workspace 1");
+        assert_eq!(server.client_fds().count(), 1);
+    }
+
+    #[test]
+    fn test_subscribe_parsing() {
+        assert!(parse_subscribe("focus_next").is_none());
+        assert!(parse_subscribe("subscribe").unwrap().unwrap().is_empty());
+
+        let kinds = parse_subscribe("subscribe focus_changed workspace_switched")
+            .unwrap()
+            .unwrap();
+        assert_eq!(kinds.len(), 2);
+        assert!(kinds.contains("focus_changed"));
+
+        assert!(parse_subscribe("subscribe focus_chnaged").unwrap().is_err());
+    }
+
+    #[test]
+    fn test_broadcast_filters_by_kind() {
+        let path = std::env::temp_dir().join(format!("rustile-sub-{}.sock", std::process::id()));
+        let mut server = IpcServer::bind(&path).unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        writeln!(client, "subscribe workspace_created").unwrap();
+        let request = server.accept_requests().remove(0);
+        let kinds = parse_subscribe(&request.message).unwrap().unwrap();
+        server.subscribe(request, kinds);
+
+        server.broadcast(&[
+            WmEvent::FocusChanged { window: Some(1) },
+            WmEvent::WorkspaceCreated { index: 1 },
+        ]);
+
+        let mut line = String::new();
+        BufReader::new(&client).read_line(&mut line).unwrap();
+        assert_eq!(line, "{\"event\":\"workspace_created\",\"index\":1}\n");
+    }
+
+    #[test]
+    fn test_stalled_subscriber_gets_whole_lines() {
+        let path = std::env::temp_dir().join(format!("rustile-stall-{}.sock", std::process::id()));
+        let mut server = IpcServer::bind(&path).unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        writeln!(client, "subscribe").unwrap();
+        let request = server.accept_requests().remove(0);
+        server.subscribe(request, HashSet::new());
+
+        // Far more than the socket buffer and the backlog, while the client reads nothing
+        let event = WmEvent::ModeChanged {
+            mode: "x".repeat(1000),
+        };
+        for _ in 0..(2 * MAX_SUBSCRIBER_BACKLOG / 1000) {
+            server.broadcast(std::slice::from_ref(&event));
+        }
+        assert!(server.subscribers[0].closing.is_some());
+
+        let reader = std::thread::spawn(move || {
+            let mut output = String::new();
+            client.read_to_string(&mut output).unwrap();
+            output
+        });
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !server.subscribers.is_empty() && Instant::now() < deadline {
+            server.flush_subscribers();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert!(server.subscribers.is_empty());
+        let output = reader.join().unwrap();
+
+        assert!(output.ends_with('\n'));
+        for line in output.lines() {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(json["event"], "mode_changed");
+        }
+    }
+
+    #[test]
+    fn test_query_parsing() {
+        assert_eq!(Query::parse("get_workspaces"), Some(Query::Workspaces));
+        assert_eq!(Query::parse("get_tree"), Some(Query::Tree));
+        assert_eq!(Query::parse("get_config"), Some(Query::Config));
+        assert_eq!(Query::parse("get_mode"), Some(Query::Mode));
+        assert_eq!(Query::parse("focus_next"), None);
+    }
+
+    #[test]
+    fn test_tree_node_serialization() {
+        let mut tree = crate::bsp::BspTree::new();
+        tree.add_window(10, None, 0.5);
+        tree.add_window(20, Some(10), 0.5);
+
+        let geometries = [WindowGeometry {
+            window: 10,
+            x: 0,
+            y: 0,
+            width: 100,
+            height: 200,
+        }];
+        let node = TreeNode::new(tree.root.as_ref().unwrap(), &geometries);
+        let json = serde_json::to_value(&node).unwrap();
+
+        assert_eq!(json["type"], "split");
+        assert_eq!(json["direction"], "horizontal");
+        assert_eq!(json["ratio"], 0.5);
+        assert_eq!(json["left"]["window"], 10);
+        assert_eq!(json["left"]["geometry"]["width"], 100);
+        assert_eq!(json["right"]["window"], 20);
+        assert!(json["right"]["geometry"].is_null());
+    }
+
+    #[test]
+    fn test_no_pending_requests() {
+        let path = std::env::temp_dir().join(format!("rustile-empty-{}.sock", std::process::id()));
+        let mut server = IpcServer::bind(&path).unwrap();
+        assert!(server.accept_requests().is_empty());
+    }
+}
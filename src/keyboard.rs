@@ -1,10 +1,26 @@
 //! Keyboard shortcut management for X11 window manager
 
 use anyhow::Result;
-use std::collections::HashMap;
-use tracing::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use x11rb::CURRENT_TIME;
 use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::ErrorKind;
+use x11rb::protocol::xinput::{self, ConnectionExt as _};
 use x11rb::protocol::xproto::*;
+use xkeysym::Keysym;
+
+use crate::command::{Action, Command, CommandChain};
+use crate::config::{DEFAULT_MODE, ModeConfig};
+
+/// Escape keysym, which leaves a mode unless the mode binds it itself
+const ESCAPE_KEYSYM: u32 = 0xff1b;
+
+/// Marks a binding that fires when its key is released: "release:Super_L"
+const RELEASE_PREFIX: &str = "release:";
 
 /// Specifies left/right requirement for modifier keys
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -14,34 +30,469 @@ pub enum ModifierSide {
     RightOnly, // Only right modifier key
 }
 
+/// Left/right requirement of each modifier that has two keys
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModifierSides {
+    pub alt: ModifierSide,
+    pub control: ModifierSide,
+    pub shift: ModifierSide,
+    pub super_key: ModifierSide,
+}
+
+impl Default for ModifierSides {
+    fn default() -> Self {
+        Self {
+            alt: ModifierSide::Either,
+            control: ModifierSide::Either,
+            shift: ModifierSide::Either,
+            super_key: ModifierSide::Either,
+        }
+    }
+}
+
+impl ModifierSides {
+    /// Each side requirement with the keysyms of the left and right key
+    fn with_keysyms(&self) -> [(ModifierSide, u32, u32); 4] {
+        [
+            (self.shift, 0xffe1, 0xffe2),     // Shift_L, Shift_R
+            (self.control, 0xffe3, 0xffe4),   // Control_L, Control_R
+            (self.alt, 0xffe9, 0xffea),       // Alt_L, Alt_R
+            (self.super_key, 0xffeb, 0xffec), // Super_L, Super_R
+        ]
+    }
+
+    /// Returns true if no modifier is restricted to one side
+    fn is_either(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
 /// Shortcut information
 #[derive(Debug, Clone)]
 pub struct Shortcut {
-    pub modifiers: ModMask,     // Bit flags for Ctrl, Alt, etc.
-    pub keycode: u8,            // Physical key position
-    pub command: String,        // Command to execute
-    pub alt_side: ModifierSide, // Alt left/right requirement
+    pub combo: String,         // Key combination as written in the config
+    pub modifiers: ModMask,    // Bit flags for Ctrl, Alt, etc.
+    pub keycode: u8,           // Physical key position
+    pub command: CommandChain, // Commands to execute
+    pub sides: ModifierSides,  // Left/right modifier requirements
+}
+
+impl Shortcut {
+    /// The key press that triggers this shortcut
+    fn step(&self) -> KeyStep {
+        KeyStep {
+            modifiers: self.modifiers,
+            keycode: self.keycode,
+            sides: self.sides,
+        }
+    }
+}
+
+/// A key as written in the config: a keysym, or a raw keycode (`code:123`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Sym(u32),
+    Code(u8),
+}
+
+/// Which ModN bit each logical modifier is on, learned from GetModifierMapping
+/// Fields are empty when the keyboard has no such modifier
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModifierMap {
+    pub alt: ModMask,
+    pub super_key: ModMask,
+    /// Only set when Hyper has a modifier of its own (not shared with Super or Alt)
+    pub hyper: ModMask,
+    pub num_lock: ModMask,
+    pub scroll_lock: ModMask,
+    /// Selects keysym columns 4 and 5 (ISO_Level3_Shift, usually AltGr)
+    pub level3: ModMask,
+}
+
+impl Default for ModifierMap {
+    /// The usual Xorg layout
+    fn default() -> Self {
+        Self {
+            alt: ModMask::M1,
+            super_key: ModMask::M4,
+            hyper: ModMask::from(0u16),
+            num_lock: ModMask::M2,
+            scroll_lock: ModMask::from(0u16),
+            level3: ModMask::M5,
+        }
+    }
+}
+
+impl ModifierMap {
+    /// Builds the map from the GetModifierMapping keycodes: 8 rows (Shift, Lock, Control,
+    /// Mod1..Mod5) of `per_modifier` keycodes each. `keycode_of` looks up a keysym's keycode.
+    fn from_mapping(
+        keycodes: &[u8],
+        per_modifier: usize,
+        keycode_of: impl Fn(u32) -> Option<u8>,
+    ) -> Self {
+        let modifier_for = |keysyms: &[u32]| -> ModMask {
+            let wanted: Vec<u8> = keysyms.iter().filter_map(|&k| keycode_of(k)).collect();
+            if per_modifier == 0 {
+                return ModMask::from(0u16);
+            }
+            keycodes
+                .chunks(per_modifier)
+                .take(8)
+                .position(|row| row.iter().any(|code| *code != 0 && wanted.contains(code)))
+                .map_or(ModMask::from(0u16), |index| ModMask::from(1u16 << index))
+        };
+
+        let defaults = Self::default();
+        let alt = first_found([
+            modifier_for(&[0xffe9, 0xffea]), // Alt_L, Alt_R
+            modifier_for(&[0xffe7, 0xffe8]), // Meta_L, Meta_R
+            defaults.alt,
+        ]);
+        let super_key = first_found([
+            modifier_for(&[0xffeb, 0xffec]), // Super_L, Super_R
+            defaults.super_key,
+        ]);
+        let mut hyper = modifier_for(&[0xffed, 0xffee]); // Hyper_L, Hyper_R
+        if hyper == super_key || hyper == alt {
+            hyper = ModMask::from(0u16);
+        }
+
+        Self {
+            alt,
+            super_key,
+            hyper,
+            num_lock: modifier_for(&[0xff7f]),    // Num_Lock
+            scroll_lock: modifier_for(&[0xff14]), // Scroll_Lock
+            level3: first_found([
+                modifier_for(&[0xfe03, 0xff7e]), // ISO_Level3_Shift, Mode_switch
+                defaults.level3,
+            ]),
+        }
+    }
+
+    /// CapsLock plus whichever modifiers NumLock and ScrollLock are on
+    fn lock_mask(&self) -> ModMask {
+        ModMask::LOCK | self.num_lock | self.scroll_lock
+    }
+
+    /// Every combination of lock modifiers; each shortcut is grabbed with all of them
+    fn lock_combinations(&self) -> Vec<ModMask> {
+        let locks = [ModMask::LOCK, self.num_lock, self.scroll_lock];
+        let mut combinations = vec![ModMask::from(0u16)];
+        for lock in locks.into_iter().filter(|&lock| !is_empty(lock)) {
+            let with_lock: Vec<ModMask> = combinations.iter().map(|&c| c | lock).collect();
+            for combination in with_lock {
+                if !combinations.contains(&combination) {
+                    combinations.push(combination);
+                }
+            }
+        }
+        combinations
+    }
+
+    /// Modifier bits compared when matching a key press: everything but the locks
+    fn relevant(&self, state: KeyButMask) -> u16 {
+        let modifiers = ModMask::SHIFT
+            | ModMask::CONTROL
+            | ModMask::M1
+            | ModMask::M2
+            | ModMask::M3
+            | ModMask::M4
+            | ModMask::M5;
+        u16::from(state) & (modifiers.bits() & !self.lock_mask().bits())
+    }
+}
+
+/// Finds the bindings of one config section that resolve to the same keys
+fn find_conflicts(section: &str, bindings: &[(&str, Vec<KeyStep>)]) -> Vec<Conflict> {
+    let mut bindings: Vec<&(&str, Vec<KeyStep>)> = bindings.iter().collect();
+    bindings.sort_by_key(|(combo, steps)| (steps.len(), *combo));
+
+    let mut conflicts = Vec::new();
+    for (i, (first, first_steps)) in bindings.iter().enumerate() {
+        for (second, second_steps) in &bindings[i + 1..] {
+            let shared = first_steps.iter().zip(second_steps.iter());
+            if !shared.clone().all(|(a, b)| steps_overlap(a, b)) {
+                continue;
+            }
+            let kind = if first_steps.len() < second_steps.len() {
+                ConflictKind::Prefix
+            } else if shared.clone().all(|(a, b)| a == b) {
+                ConflictKind::Duplicate
+            } else {
+                ConflictKind::Overlap
+            };
+            conflicts.push(Conflict {
+                section: section.to_string(),
+                first: first.to_string(),
+                second: second.to_string(),
+                kind,
+            });
+        }
+    }
+    conflicts
+}
+
+/// Returns true if some key press matches both steps
+fn steps_overlap(a: &KeyStep, b: &KeyStep) -> bool {
+    let sides_overlap = |a: ModifierSide, b: ModifierSide| {
+        a == b || a == ModifierSide::Either || b == ModifierSide::Either
+    };
+    a.modifiers == b.modifiers
+        && a.keycode == b.keycode
+        && a.sides
+            .with_keysyms()
+            .iter()
+            .zip(b.sides.with_keysyms())
+            .all(|(a, b)| sides_overlap(a.0, b.0))
+}
+
+/// Side named by a modifier like "Super_L" or "ctrl_r"
+fn side_of(modifier: &str) -> ModifierSide {
+    if modifier.to_lowercase().ends_with("_r") {
+        ModifierSide::RightOnly
+    } else {
+        ModifierSide::LeftOnly
+    }
+}
+
+/// Returns true for a mask without any modifier bit
+fn is_empty(mask: ModMask) -> bool {
+    mask.bits() == 0
+}
+
+/// Returns the first mask that has a modifier, falling back to the last one
+fn first_found<const N: usize>(masks: [ModMask; N]) -> ModMask {
+    masks
+        .into_iter()
+        .find(|&mask| !is_empty(mask))
+        .unwrap_or(masks[N - 1])
+}
+
+/// Where a keysym sits on the keyboard
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct KeyPosition {
+    keycode: u8,
+    /// Keysym column: 0 plain, 1 Shift, 4 level 3, 5 Shift + level 3
+    column: usize,
+}
+
+/// One key press of a key sequence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyStep {
+    pub modifiers: ModMask,
+    pub keycode: u8,
+    pub sides: ModifierSides,
+}
+
+/// Multi-key binding such as "Super+w h": the first step is grabbed on the root window,
+/// the following ones are read while the keyboard is grabbed
+#[derive(Debug, Clone)]
+pub struct KeySequence {
+    pub combo: String,
+    pub steps: Vec<KeyStep>,
+    pub command: CommandChain,
+}
+
+/// Progress through the key sequences whose prefix has been pressed
+#[derive(Debug)]
+struct PendingSequence {
+    /// Indices of the sequences still matching the keys pressed so far
+    candidates: Vec<usize>,
+    /// Number of steps already pressed
+    pressed: usize,
+    /// The sequence is cancelled if the next key is not pressed before this
+    deadline: Instant,
+}
+
+/// What to do with a key press, which decides the AllowEvents mode
+#[derive(Debug, PartialEq)]
+pub enum KeyAction<'a> {
+    /// A binding matched: run its commands
+    Run(&'a CommandChain),
+    /// A key sequence is in progress: deliver the next key to us too
+    Wait,
+    /// Swallow the key (unbound key inside a mode, or Escape cancelling a sequence)
+    Ignore,
+    /// Not a binding: replay the key to the focused client
+    Replay,
+    /// Escape while a held binding (like alt-tab) is active: abandon it
+    Cancel,
+}
+
+/// How two bindings of the same section get in each other's way
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictKind {
+    /// Same keys: only one of them can ever fire
+    Duplicate,
+    /// Same keys, but one is restricted to a left or right modifier: it wins while that side is held
+    Overlap,
+    /// The first binding completes before the second one's next key
+    Prefix,
+}
+
+/// Two bindings that resolve to the same keys
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// "shortcuts" or "modes.<name>"
+    pub section: String,
+    pub first: String,
+    pub second: String,
+    pub kind: ConflictKind,
+}
+
+impl std::fmt::Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (section, first, second) = (&self.section, &self.first, &self.second);
+        match self.kind {
+            ConflictKind::Duplicate => write!(
+                f,
+                "[{section}] \"{first}\" and \"{second}\" are the same keys, only one of them can fire"
+            ),
+            ConflictKind::Overlap => write!(
+                f,
+                "[{section}] \"{first}\" and \"{second}\" overlap, the one with a left/right modifier wins while that key is held"
+            ),
+            ConflictKind::Prefix => write!(
+                f,
+                "[{section}] \"{first}\" fires before key sequence \"{second}\" can continue"
+            ),
+        }
+    }
+}
+
+/// Outcome of registering the bindings, shown at startup and by `rustile --check-config`
+#[derive(Debug, Default)]
+pub struct BindingSummary {
+    pub shortcuts: usize,
+    pub sequences: usize,
+    pub modes: usize,
+    /// Bindings that could not be parsed, with the reason
+    pub invalid: Vec<String>,
+    pub conflicts: Vec<Conflict>,
+    /// Combinations another client had already grabbed (BadAccess)
+    pub grab_failures: Vec<String>,
+}
+
+impl BindingSummary {
+    /// Returns true if some binding can never fire
+    pub fn has_errors(&self) -> bool {
+        !self.invalid.is_empty()
+            || !self.grab_failures.is_empty()
+            || self
+                .conflicts
+                .iter()
+                .any(|conflict| conflict.kind != ConflictKind::Overlap)
+    }
+
+    /// Every problem, one line each
+    pub fn problems(&self) -> Vec<String> {
+        let invalid = self.invalid.iter().map(|e| format!("Invalid binding {e}"));
+        let conflicts = self.conflicts.iter().map(ToString::to_string);
+        let grabs = self
+            .grab_failures
+            .iter()
+            .map(|combo| format!("\"{combo}\" is already grabbed by another client"));
+        invalid.chain(conflicts).chain(grabs).collect()
+    }
+}
+
+impl std::fmt::Display for BindingSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} shortcuts, {} key sequences, {} modes; {} invalid, {} conflicts, {} failed grabs",
+            self.shortcuts,
+            self.sequences,
+            self.modes,
+            self.invalid.len(),
+            self.conflicts.len(),
+            self.grab_failures.len()
+        )
+    }
 }
 
 /// Manages keyboard shortcuts from configuration to X11 event handling
 pub struct ShortcutManager {
     /// Map of keynames to keysym values for parsing config
     /// Example: "q" → 0x0071, "Return" → 0xff0d
-    keyname_to_keysym: HashMap<String, u32>,
-    /// Map of keysym values to keycodes from X11
-    /// Example: 0x0071 ('q') → 24, 0x0061 ('a') → 38
-    keysym_to_keycode: HashMap<u32, u8>,
+    keyname_to_keysym: &'static HashMap<String, u32>,
+    /// Map of keysym values to keycodes from X11, refreshed on MappingNotify
+    /// Example: 0x0071 ('q') → 24, 0x0021 ('!') → 10 with Shift
+    keysym_to_keycode: HashMap<u32, KeyPosition>,
     /// Registered shortcuts
     shortcuts: Vec<Shortcut>,
-    /// Detected keycode for left Alt (keysym 0xffe9)
-    alt_l_keycode: Option<u8>,
-    /// Detected keycode for right Alt (keysym 0xffea)
-    alt_r_keycode: Option<u8>,
+    /// Shortcuts of each named mode, matched while the keyboard is grabbed
+    modes: HashMap<String, Vec<Shortcut>>,
+    /// Mode currently entered, None for the default bindings
+    active_mode: Option<String>,
+    /// Registered multi-key sequences
+    sequences: Vec<KeySequence>,
+    /// Bindings fired when their key is released ("release:Super_L"), matched from XInput events
+    release_shortcuts: Vec<Shortcut>,
+    /// Last key pressed; a release binding only fires if no other key was pressed in between
+    last_pressed: Option<u8>,
+    /// Modifiers of the last key press that ran a binding
+    last_modifiers: u16,
+    /// Modifiers that keep a held binding (like alt-tab) going, while the keyboard is grabbed for it
+    hold: Option<u16>,
+    /// Sequence whose prefix has been pressed, if any
+    pending: Option<PendingSequence>,
+    /// How long to wait for the next key of a sequence
+    sequence_timeout: Duration,
+    /// Keycodes of modifier keys, which never end a pending sequence
+    modifier_keycodes: HashSet<u8>,
+    /// Which ModN bits Alt, Super, NumLock, ... are on
+    modifier_map: ModifierMap,
+    /// Modifier bit each modifier key sets, from GetModifierMapping
+    keycode_modifiers: HashMap<u8, ModMask>,
+    /// Top-level bindings that failed to parse, with the reason
+    invalid_shortcuts: Vec<String>,
+    /// Mode bindings that failed to parse, with the reason
+    invalid_mode_shortcuts: Vec<String>,
+    /// Combinations whose grab failed because another client holds them
+    grab_failures: Vec<String>,
+    /// Keys currently held down (one bit per keycode), kept up to date from XInput raw
+    /// key events; None if XInput 2 is unavailable and QueryKeymap is asked instead
+    pressed_keys: Option<[u8; 32]>,
 }
 
 impl ShortcutManager {
     /// Creates a new shortcut manager and initializes keysym-to-keycode mapping
     pub fn new<C: Connection>(conn: &C, setup: &Setup) -> Result<Self> {
+        let mut manager = Self {
+            keyname_to_keysym: Self::build_keysym_table(),
+            keysym_to_keycode: HashMap::new(),
+            shortcuts: Vec::new(),
+            modes: HashMap::new(),
+            active_mode: None,
+            sequences: Vec::new(),
+            release_shortcuts: Vec::new(),
+            last_pressed: None,
+            last_modifiers: 0,
+            hold: None,
+            pending: None,
+            sequence_timeout: Duration::from_millis(1000),
+            modifier_keycodes: HashSet::new(),
+            modifier_map: ModifierMap::default(),
+            keycode_modifiers: HashMap::new(),
+            invalid_shortcuts: Vec::new(),
+            invalid_mode_shortcuts: Vec::new(),
+            grab_failures: Vec::new(),
+            pressed_keys: None,
+        };
+        manager.load_keyboard_mapping(conn, setup)?;
+        Ok(manager)
+    }
+
+    /// Re-reads the keyboard mapping after a MappingNotify; shortcuts must be registered again
+    pub fn refresh_mapping<C: Connection>(&mut self, conn: &C) -> Result<()> {
+        self.load_keyboard_mapping(conn, conn.setup())
+    }
+
+    /// Fetches the keyboard mapping from the X server and derives the keycode tables from it
+    fn load_keyboard_mapping<C: Connection>(&mut self, conn: &C, setup: &Setup) -> Result<()> {
         let min_keycode = setup.min_keycode;
         let max_keycode = setup.max_keycode;
 
@@ -54,89 +505,83 @@ impl ShortcutManager {
             .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
             .reply()?;
 
-        // Each physical key can produce multiple symbols
-        // Example: keycode 38 → [0x0061 ('a'), 0x0041 ('A'), 0x00e1 ('á'), 0x00c1 ('Á')]
-        // depending on which modifiers (none, Shift, etc.) are pressed
-        let keysyms_per_keycode = mapping_reply.keysyms_per_keycode as usize;
-        let mut keysym_to_keycode = HashMap::new();
-
-        // Build reverse map: keysym → keycode
-        // This allows us to convert keynames to keycodes
-        // Example flow: "q" → 0x0071 → keycode 24
-        for (index, chunk) in mapping_reply
-            .keysyms
-            .chunks(keysyms_per_keycode)
-            .enumerate()
-        {
-            // Calculate the actual keycode for this chunk
-            let keycode = min_keycode + index as u8;
-
-            // Store only the first keysym (unmodified position) for each keycode
-            // Example: chunk = [0x0061 ('a'), 0x0041 ('A'), 0x00e1 ('á'), 0x00c1 ('Á')]
-            // We store: keysym_to_keycode.insert(0x0061, 38)
-            // This creates mapping: 0x0061 → keycode 38
-            if let Some(&keysym) = chunk.first()
-                && keysym != 0
-            {
-                keysym_to_keycode.insert(keysym, keycode);
-            }
-        }
-
+        self.keysym_to_keycode = build_keycode_map(
+            min_keycode,
+            mapping_reply.keysyms_per_keycode as usize,
+            &mapping_reply.keysyms,
+        );
         info!(
-            "Initialized shortcut manager with {} keycodes",
-            keysym_to_keycode.len()
+            "Loaded keyboard mapping with {} keysyms",
+            self.keysym_to_keycode.len()
         );
 
-        // Detect Alt_L and Alt_R keycodes for left/right distinction
-        let alt_l_keycode = keysym_to_keycode.get(&0xffe9).copied(); // Alt_L keysym
-        let alt_r_keycode = keysym_to_keycode.get(&0xffea).copied(); // Alt_R keysym
-        info!("Detected Alt_L keycode: {:?}", alt_l_keycode);
-        info!("Detected Alt_R keycode: {:?}", alt_r_keycode);
+        // Shift_L (0xffe1) through Hyper_R (0xffee), plus ISO_Level3_Shift (0xfe03)
+        self.modifier_keycodes = (0xffe1..=0xffee)
+            .chain([0xfe03])
+            .filter_map(|keysym| self.get_keycode(keysym).ok())
+            .collect();
+
+        let modifier_mapping = conn.get_modifier_mapping()?.reply()?;
+        self.modifier_map = ModifierMap::from_mapping(
+            &modifier_mapping.keycodes,
+            usize::from(modifier_mapping.keycodes_per_modifier()),
+            |keysym| self.get_keycode(keysym).ok(),
+        );
+        info!("Detected modifier mapping: {:?}", self.modifier_map);
+
+        // Row N of the mapping lists the keys that set modifier bit N
+        let per_modifier = usize::from(modifier_mapping.keycodes_per_modifier()).max(1);
+        self.keycode_modifiers = modifier_mapping
+            .keycodes
+            .chunks(per_modifier)
+            .take(8)
+            .enumerate()
+            .flat_map(|(index, row)| {
+                row.iter()
+                    .filter(|&&keycode| keycode != 0)
+                    .map(move |&keycode| (keycode, ModMask::from(1u16 << index)))
+            })
+            .collect();
 
-        Ok(Self {
-            keyname_to_keysym: Self::build_keysym_table(),
-            keysym_to_keycode,
-            shortcuts: Vec::new(),
-            alt_l_keycode,
-            alt_r_keycode,
-        })
+        Ok(())
     }
 
-    /// Builds the keyname-to-keysym mapping table
-    fn build_keysym_table() -> HashMap<String, u32> {
-        let mut keyname_to_keysym = HashMap::new();
+    /// Builds the keyname-to-keysym mapping table from every keysym name known to xkeysym
+    /// Names are X's own: "bracketleft", "KP_Enter", "F24", "XF86AudioRaiseVolume"
+    /// Built once per process, since it scans tens of thousands of keysyms
+    fn build_keysym_table() -> &'static HashMap<String, u32> {
+        static TABLE: LazyLock<HashMap<String, u32>> =
+            LazyLock::new(ShortcutManager::scan_keysym_names);
+        &TABLE
+    }
 
-        // Letters: "a" → 0x0061 (97), "b" → 0x0062 (98), etc.
-        for c in 'a'..='z' {
-            keyname_to_keysym.insert(c.to_string(), c as u32);
-        }
+    /// Collects the name of every keysym xkeysym knows
+    fn scan_keysym_names() -> HashMap<String, u32> {
+        // Ranges holding named keysyms: Latin and function keys, Unicode keysyms,
+        // vendor keysyms (DEC, HP, OSF, Sun) and XF86 media keys
+        let ranges = [
+            0x0000..=0xffff,
+            0x0100_0100..=0x0100_28ff,
+            0x1000_0000..=0x1000_00ff,
+            0x1000_fe00..=0x1000_ffff,
+            0x1004_ff00..=0x1004_ffff,
+            0x1005_ff00..=0x1005_ffff,
+            0x1008_1000..=0x1008_12ff,
+            0x1008_fe00..=0x1008_ffff,
+        ];
 
-        // Numbers: "0" → 0x0030 (48), "1" → 0x0031 (49), etc.
-        for c in '0'..='9' {
-            keyname_to_keysym.insert(c.to_string(), c as u32);
+        let mut keyname_to_keysym = HashMap::new();
+        for raw in ranges.into_iter().flatten() {
+            // xkeysym reports C macro names: "XK_Return" → "Return", "XF86XK_Mail" → "XF86Mail"
+            if let Some(name) = Keysym::new(raw).name() {
+                keyname_to_keysym
+                    .entry(name.replacen("XK_", "", 1))
+                    .or_insert(raw);
+            }
         }
 
-        // Special keys with their keysym values
-        keyname_to_keysym.insert("Return".to_string(), 0xff0d); // Return key
+        // Friendlier alias kept from the original table
         keyname_to_keysym.insert("Enter".to_string(), 0xff0d); // Same as Return
-        keyname_to_keysym.insert("space".to_string(), 0x0020); // Space bar (32)
-        keyname_to_keysym.insert("Tab".to_string(), 0xff09); // Tab key
-        keyname_to_keysym.insert("Escape".to_string(), 0xff1b); // Escape key
-        keyname_to_keysym.insert("BackSpace".to_string(), 0xff08); // Backspace key
-        keyname_to_keysym.insert("Delete".to_string(), 0xffff); // Delete key
-        keyname_to_keysym.insert("Home".to_string(), 0xff50); // Home key
-        keyname_to_keysym.insert("End".to_string(), 0xff57); // End key
-        keyname_to_keysym.insert("Page_Up".to_string(), 0xff55); // Page Up key
-        keyname_to_keysym.insert("Page_Down".to_string(), 0xff56); // Page Down key
-        keyname_to_keysym.insert("Left".to_string(), 0xff51); // Left arrow
-        keyname_to_keysym.insert("Up".to_string(), 0xff52); // Up arrow
-        keyname_to_keysym.insert("Right".to_string(), 0xff53); // Right arrow
-        keyname_to_keysym.insert("Down".to_string(), 0xff54);
-
-        // Function keys: "F1" → 0xffbe, "F2" → 0xffbf, ..., "F12" → 0xffc9
-        for i in 1..=12 {
-            keyname_to_keysym.insert(format!("F{i}"), 0xffbe + i - 1);
-        }
 
         keyname_to_keysym
     }
@@ -148,195 +593,783 @@ impl ShortcutManager {
         root_window: Window,
         shortcuts_config: &HashMap<String, String>,
     ) -> Result<()> {
+        self.load_shortcuts(shortcuts_config);
+
+        // Sequences sharing a prefix grab the same key again, which X allows for the same client
+        let grabs: Vec<(String, KeyStep)> = self
+            .shortcuts
+            .iter()
+            .map(|shortcut| (shortcut.combo.clone(), shortcut.step()))
+            .chain(
+                self.sequences
+                    .iter()
+                    .map(|sequence| (sequence.combo.clone(), sequence.steps[0])),
+            )
+            .collect();
+
+        self.grab_failures.clear();
+        for (combo, step) in grabs {
+            if !self.grab_step(conn, root_window, step)? {
+                error!(
+                    "Failed to grab {}: already grabbed by another client",
+                    combo
+                );
+                self.grab_failures.push(combo);
+            }
+        }
+
+        if !self.release_shortcuts.is_empty() && self.pressed_keys.is_none() {
+            error!("Release bindings need the XInput 2 extension and will not fire");
+        }
+
+        info!(
+            "Registered {} shortcuts, {} release bindings and {} key sequences",
+            self.shortcuts.len(),
+            self.release_shortcuts.len(),
+            self.sequences.len()
+        );
+        Ok(())
+    }
+
+    /// Parses the top-level shortcuts without grabbing them
+    pub fn load_shortcuts(&mut self, shortcuts_config: &HashMap<String, String>) {
         self.shortcuts.clear();
+        self.sequences.clear();
+        self.release_shortcuts.clear();
+        self.invalid_shortcuts.clear();
 
         for (key_combo, command) in shortcuts_config {
-            match self.register_shortcut(conn, root_window, key_combo, command) {
+            match self.load_shortcut(key_combo, command) {
                 Ok(()) => {
                     info!("Registered shortcut: {} -> {}", key_combo, command);
                 }
                 Err(e) => {
                     error!("Failed to register shortcut {}: {}", key_combo, e);
+                    self.invalid_shortcuts.push(format!("{key_combo}: {e}"));
                 }
             }
         }
 
-        info!("Registered {} shortcuts", self.shortcuts.len());
-        Ok(())
+        // "Super_R+Return" is tried before "Super+Return", which would match it too
+        self.shortcuts
+            .sort_by_key(|shortcut| shortcut.sides.is_either());
+        self.sequences
+            .sort_by_key(|sequence| sequence.steps[0].sides.is_either());
     }
 
-    /// Registers a single shortcut
-    fn register_shortcut<C: Connection>(
+    /// Summarizes the registered bindings and everything wrong with them
+    pub fn summary(&self) -> BindingSummary {
+        let mut top_level: Vec<(&str, Vec<KeyStep>)> = self
+            .shortcuts
+            .iter()
+            .map(|shortcut| (shortcut.combo.as_str(), vec![shortcut.step()]))
+            .collect();
+        top_level.extend(
+            self.sequences
+                .iter()
+                .map(|sequence| (sequence.combo.as_str(), sequence.steps.clone())),
+        );
+        let mut conflicts = find_conflicts("shortcuts", &top_level);
+        let release: Vec<(&str, Vec<KeyStep>)> = self
+            .release_shortcuts
+            .iter()
+            .map(|shortcut| (shortcut.combo.as_str(), vec![shortcut.step()]))
+            .collect();
+        conflicts.extend(find_conflicts("shortcuts", &release));
+
+        let mut modes: Vec<_> = self.modes.iter().collect();
+        modes.sort_by_key(|(name, _)| name.as_str());
+        for (name, shortcuts) in modes {
+            let bindings: Vec<(&str, Vec<KeyStep>)> = shortcuts
+                .iter()
+                .map(|shortcut| (shortcut.combo.as_str(), vec![shortcut.step()]))
+                .collect();
+            conflicts.extend(find_conflicts(&format!("modes.{name}"), &bindings));
+        }
+
+        let mut invalid = self.invalid_shortcuts.clone();
+        invalid.extend(self.invalid_mode_shortcuts.iter().cloned());
+        invalid.sort();
+
+        BindingSummary {
+            shortcuts: self.shortcuts.len() + self.release_shortcuts.len(),
+            sequences: self.sequences.len(),
+            modes: self.modes.len(),
+            invalid,
+            conflicts,
+            grab_failures: self.grab_failures.clone(),
+        }
+    }
+
+    /// Logs the binding summary, with one line per problem
+    pub fn log_summary(&self) {
+        let summary = self.summary();
+        info!("Key bindings: {}", summary);
+        for problem in summary.problems() {
+            warn!("{}", problem);
+        }
+    }
+
+    /// Sets how long a key sequence waits for its next key
+    pub fn set_sequence_timeout(&mut self, timeout: Duration) {
+        self.sequence_timeout = timeout;
+    }
+
+    /// Releases the key grabs of all registered shortcuts and leaves any active mode
+    pub fn unregister_shortcuts<C: Connection>(
         &mut self,
         conn: &C,
         root_window: Window,
-        key_combo: &str,
-        command: &str,
     ) -> Result<()> {
-        // Parse key combination string into modifiers, keysym, and Alt side requirement
-        // Example: "Super+q" → (ModMask::M4, 0x0071, ModifierSide::Either)
-        // Example: "Alt_L+t" → (ModMask::M1, 0x0074, ModifierSide::LeftOnly)
-        let (modifiers, keysym, alt_side) = self.parse_key_combination(key_combo)?;
+        self.leave_mode(conn)?;
+        self.cancel_sequence(conn)?;
+        self.end_hold(conn)?;
+        // ANY also covers the lock variants, even if the modifier mapping changed since
+        for shortcut in self.shortcuts.drain(..) {
+            conn.ungrab_key(shortcut.keycode, root_window, ModMask::ANY)?;
+        }
+        for sequence in self.sequences.drain(..) {
+            conn.ungrab_key(sequence.steps[0].keycode, root_window, ModMask::ANY)?;
+        }
+        Ok(())
+    }
 
-        // Convert keysym to keycode using our mapping
-        // Example: 0x0071 ('q') → keycode 24
-        let keycode = self.get_keycode(keysym)?;
+    /// Parses a single shortcut or key sequence and stores it
+    fn load_shortcut(&mut self, key_combo: &str, command: &str) -> Result<()> {
+        if let Some(release_combo) = key_combo.strip_prefix(RELEASE_PREFIX) {
+            let step = self.parse_release(release_combo)?;
+            self.release_shortcuts.push(Shortcut {
+                combo: key_combo.to_string(),
+                modifiers: step.modifiers,
+                keycode: step.keycode,
+                command: CommandChain::parse(command)?,
+                sides: step.sides,
+            });
+            return Ok(());
+        }
 
-        // Tell X11 to send us KeyPress events when this combination is pressed
-        // Use SYNC mode to enable event replay for unmatched shortcuts (see ADR-015)
-        conn.grab_key(
-            true,
-            root_window,
-            modifiers,
-            keycode,
-            GrabMode::SYNC,
-            GrabMode::SYNC,
-        )?;
+        let steps = self.parse_sequence(key_combo)?;
+
+        // Parse the bound command (already validated at config load)
+        let command = CommandChain::parse(command)?;
 
         // Store the shortcut for later lookup when we receive key events
-        self.shortcuts.push(Shortcut {
-            modifiers,
+        let combo = key_combo.to_string();
+        if steps.len() > 1 {
+            self.sequences.push(KeySequence {
+                combo,
+                steps,
+                command,
+            });
+        } else {
+            let first = steps[0];
+            self.shortcuts.push(Shortcut {
+                combo,
+                modifiers: first.modifiers,
+                keycode: first.keycode,
+                command,
+                sides: first.sides,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Grabs the key of a shortcut (or sequence prefix) on the root window
+    /// Returns false if another client already grabbed it
+    fn grab_step<C: Connection>(
+        &self,
+        conn: &C,
+        root_window: Window,
+        step: KeyStep,
+    ) -> Result<bool> {
+        // Tell X11 to send us KeyPress events when this combination (or sequence prefix) is pressed
+        // Use SYNC mode to enable event replay for unmatched shortcuts (see ADR-015)
+        // Grab once per combination of CapsLock/NumLock/ScrollLock so the locks don't block it
+        let mut cookies = Vec::new();
+        for locks in self.modifier_map.lock_combinations() {
+            cookies.push(conn.grab_key(
+                true,
+                root_window,
+                step.modifiers | locks,
+                step.keycode,
+                GrabMode::SYNC,
+                GrabMode::SYNC,
+            )?);
+        }
+
+        let mut grabbed = true;
+        for cookie in cookies {
+            match cookie.check() {
+                Ok(()) => {}
+                Err(ReplyError::X11Error(e)) if e.error_kind == ErrorKind::Access => {
+                    grabbed = false;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(grabbed)
+    }
+
+    /// Parses a key combination and its bound command into a single-key shortcut
+    fn parse_shortcut(&self, key_combo: &str, command: &str) -> Result<Shortcut> {
+        let steps = self.parse_sequence(key_combo)?;
+        let [step] = steps[..] else {
+            return Err(anyhow::anyhow!(
+                "Key sequences are only supported in [shortcuts]: {}",
+                key_combo
+            ));
+        };
+
+        Ok(Shortcut {
+            combo: key_combo.to_string(),
+            modifiers: step.modifiers,
+            keycode: step.keycode,
+            command: CommandChain::parse(command)?,
+            sides: step.sides,
+        })
+    }
+
+    /// Parses a space-separated key sequence like "Super+w h" into its steps
+    fn parse_sequence(&self, key_combo: &str) -> Result<Vec<KeyStep>> {
+        let steps = split_sequence(key_combo);
+        if steps.is_empty() {
+            return Err(anyhow::anyhow!("Empty key combination"));
+        }
+
+        steps
+            .iter()
+            .map(|step| {
+                // Parse key combination string into modifiers, keysym, and side requirements
+                // Example: "Super+q" → (ModMask::M4, 0x0071, any side)
+                // Example: "Alt_L+t" → (ModMask::M1, 0x0074, left Alt only)
+                let (modifiers, key, sides) = self.parse_key_combination(step)?;
+
+                // Convert keysym to keycode using our mapping, adding Shift or level 3 if needed
+                // Example: 0x0071 ('q') → keycode 24
+                let (keycode, level_modifiers) = self.resolve_key(key)?;
+
+                Ok(KeyStep {
+                    modifiers: modifiers | level_modifiers,
+                    keycode,
+                    sides,
+                })
+            })
+            .collect()
+    }
+
+    /// Parses the key of a release binding, which may be a modifier key of its own ("Super_L")
+    fn parse_release(&self, combo: &str) -> Result<KeyStep> {
+        let (modifiers, key, sides) = self.parse_combination(combo, true)?;
+        let (keycode, level_modifiers) = self.resolve_key(key)?;
+        Ok(KeyStep {
+            modifiers: modifiers | level_modifiers,
             keycode,
-            command: command.to_string(),
-            alt_side,
-        });
+            sides,
+        })
+    }
 
+    /// Registers the bindings of every mode; they are not grabbed until the mode is entered
+    /// Escape leaves a mode unless the mode binds it
+    pub fn register_modes(&mut self, modes: &HashMap<String, ModeConfig>) {
+        self.modes.clear();
+        self.invalid_mode_shortcuts.clear();
+
+        for (name, mode) in modes {
+            let mut shortcuts = Vec::new();
+            for (key_combo, command) in &mode.shortcuts {
+                match self.parse_shortcut(key_combo, command) {
+                    Ok(shortcut) => shortcuts.push(shortcut),
+                    Err(e) => {
+                        error!("Failed to register {} shortcut {}: {}", name, key_combo, e);
+                        self.invalid_mode_shortcuts
+                            .push(format!("{key_combo} in mode {name}: {e}"));
+                    }
+                }
+            }
+
+            if let Ok(escape) = self.get_keycode(ESCAPE_KEYSYM)
+                && !shortcuts
+                    .iter()
+                    .any(|s| s.keycode == escape && s.modifiers == ModMask::from(0u16))
+            {
+                shortcuts.push(Shortcut {
+                    combo: "Escape".to_string(),
+                    modifiers: ModMask::from(0u16),
+                    keycode: escape,
+                    command: CommandChain(vec![Action::Run(Command::Mode(
+                        DEFAULT_MODE.to_string(),
+                    ))]),
+                    sides: ModifierSides::default(),
+                });
+            }
+
+            shortcuts.sort_by_key(|shortcut| shortcut.sides.is_either());
+            info!("Registered {} shortcuts for mode {}", shortcuts.len(), name);
+            self.modes.insert(name.clone(), shortcuts);
+        }
+    }
+
+    /// Gets the name of the active mode
+    pub fn active_mode(&self) -> &str {
+        self.active_mode.as_deref().unwrap_or(DEFAULT_MODE)
+    }
+
+    /// Enters a mode, grabbing the whole keyboard so unmodified keys reach us
+    /// Entering `default` leaves the current mode
+    pub fn enter_mode<C: Connection>(
+        &mut self,
+        conn: &C,
+        root_window: Window,
+        mode: &str,
+    ) -> Result<()> {
+        if mode == DEFAULT_MODE {
+            return self.leave_mode(conn);
+        }
+        if !self.modes.contains_key(mode) {
+            return Err(anyhow::anyhow!("Unknown mode: {}", mode));
+        }
+
+        if self.active_mode.is_none() {
+            let reply = conn
+                .grab_keyboard(
+                    false,
+                    root_window,
+                    CURRENT_TIME,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                )?
+                .reply()?;
+            if reply.status != GrabStatus::SUCCESS {
+                return Err(anyhow::anyhow!(
+                    "Could not grab the keyboard for mode {}: {:?}",
+                    mode,
+                    reply.status
+                ));
+            }
+        }
+
+        self.active_mode = Some(mode.to_string());
         Ok(())
     }
 
-    /// Handles a key press event and returns the command if a shortcut matches
-    /// Returns (matched_command, did_match) tuple for AllowEvents decision
+    /// Leaves the active mode and releases the keyboard grab
+    pub fn leave_mode<C: Connection>(&mut self, conn: &C) -> Result<()> {
+        if self.active_mode.take().is_some() {
+            conn.ungrab_keyboard(CURRENT_TIME)?;
+        }
+        Ok(())
+    }
+
+    /// Handles a key press event and decides what to do with it
+    /// In a mode every key is ours: unbound keys are swallowed, not replayed
     pub fn handle_key_press<C: Connection>(
-        &self,
+        &mut self,
         conn: &C,
         event: &KeyPressEvent,
-    ) -> Result<(Option<&str>, bool)> {
-        // Filter out lock keys (NumLock, CapsLock, ScrollLock) so they don't break shortcuts
-        let relevant_modifiers = ModMask::SHIFT.bits()
-            | ModMask::CONTROL.bits()
-            | ModMask::M1.bits()
-            | ModMask::M4.bits();
-        let event_modifiers_bits = event.state.bits() & relevant_modifiers;
+    ) -> Result<KeyAction<'_>> {
+        if self.pending.is_some() {
+            return self.continue_sequence(conn, event);
+        }
+
+        let shortcuts = match &self.active_mode {
+            Some(mode) => self.modes.get(mode).map_or(&[][..], Vec::as_slice),
+            None => &self.shortcuts[..],
+        };
 
         // Match event against stored shortcuts (both modifiers and keycode must match)
-        for shortcut in &self.shortcuts {
-            if event_modifiers_bits != shortcut.modifiers.bits() {
-                continue;
+        for shortcut in shortcuts {
+            if self.step_matches(conn, shortcut.step(), event)? {
+                self.last_modifiers = self.modifier_map.relevant(event.state);
+                return Ok(KeyAction::Run(&shortcut.command));
             }
-            if event.detail != shortcut.keycode {
-                continue;
+        }
+
+        // The keyboard is grabbed for the held binding, so other keys must not be replayed
+        if self.hold.is_some() {
+            let escape = self.get_keycode(ESCAPE_KEYSYM).ok();
+            return Ok(if Some(event.detail) == escape {
+                KeyAction::Cancel
+            } else {
+                KeyAction::Ignore
+            });
+        }
+
+        if self.active_mode.is_some() {
+            return Ok(KeyAction::Ignore);
+        }
+
+        let mut candidates = Vec::new();
+        for (index, sequence) in self.sequences.iter().enumerate() {
+            if self.step_matches(conn, sequence.steps[0], event)? {
+                candidates.push(index);
             }
+        }
+        if candidates.is_empty() {
+            return Ok(KeyAction::Replay);
+        }
 
-            // If Alt is in modifiers, check left/right requirement
-            if shortcut.modifiers.contains(ModMask::M1)
-                && !self.query_alt_side_match(conn, shortcut.alt_side)?
-            {
-                continue;
+        // Keep the keyboard after the prefix is released; SYNC so unrelated keys can be replayed
+        let reply = conn
+            .grab_keyboard(
+                false,
+                event.root,
+                event.time,
+                GrabMode::ASYNC,
+                GrabMode::SYNC,
+            )?
+            .reply()?;
+        if reply.status != GrabStatus::SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Could not grab the keyboard for a key sequence: {:?}",
+                reply.status
+            ));
+        }
+
+        self.pending = Some(PendingSequence {
+            candidates,
+            pressed: 1,
+            deadline: Instant::now() + self.sequence_timeout,
+        });
+        Ok(KeyAction::Wait)
+    }
+
+    /// Advances the pending key sequence with the next key press
+    fn continue_sequence<C: Connection>(
+        &mut self,
+        conn: &C,
+        event: &KeyPressEvent,
+    ) -> Result<KeyAction<'_>> {
+        // Modifiers of the next step are pressed on their own before its key
+        if self.modifier_keycodes.contains(&event.detail) {
+            return Ok(KeyAction::Wait);
+        }
+
+        let Some(pending) = self.pending.take() else {
+            return Ok(KeyAction::Replay);
+        };
+        let mut candidates = Vec::new();
+        for index in pending.candidates {
+            if self.step_matches(conn, self.sequences[index].steps[pending.pressed], event)? {
+                candidates.push(index);
+            }
+        }
+
+        if candidates.is_empty() {
+            let escape = self.get_keycode(ESCAPE_KEYSYM).ok();
+            if Some(event.detail) == escape && self.modifier_map.relevant(event.state) == 0 {
+                info!("Key sequence cancelled");
+                conn.ungrab_keyboard(CURRENT_TIME)?;
+                return Ok(KeyAction::Ignore);
             }
+            // Replaying the key also releases the keyboard grab
+            return Ok(KeyAction::Replay);
+        }
+
+        let pressed = pending.pressed + 1;
+        if let Some(&done) = candidates
+            .iter()
+            .find(|&&index| self.sequences[index].steps.len() == pressed)
+        {
+            conn.ungrab_keyboard(CURRENT_TIME)?;
+            return Ok(KeyAction::Run(&self.sequences[done].command));
+        }
+
+        self.pending = Some(PendingSequence {
+            candidates,
+            pressed,
+            deadline: Instant::now() + self.sequence_timeout,
+        });
+        Ok(KeyAction::Wait)
+    }
+
+    /// Returns true while a key sequence waits for its next key
+    pub fn is_sequence_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Gets the time the pending key sequence times out
+    pub fn sequence_deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|pending| pending.deadline)
+    }
+
+    /// Cancels the pending key sequence if its timeout has passed
+    pub fn expire_sequence<C: Connection>(&mut self, conn: &C) -> Result<()> {
+        if self
+            .sequence_deadline()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            info!("Key sequence timed out");
+            self.cancel_sequence(conn)?;
+        }
+        Ok(())
+    }
 
-            return Ok((Some(&shortcut.command), true));
+    /// Drops the pending key sequence and releases the keyboard
+    fn cancel_sequence<C: Connection>(&mut self, conn: &C) -> Result<()> {
+        if self.pending.take().is_some() {
+            conn.ungrab_keyboard(CURRENT_TIME)?;
         }
-        Ok((None, false))
+        Ok(())
     }
 
-    /// Checks if the Alt key side requirement matches current state
-    /// Uses X11 QueryKeymap to detect which Alt key is actually pressed
-    fn query_alt_side_match<C: Connection>(
+    /// Checks a key press against one key step, including left/right modifier requirements
+    fn step_matches<C: Connection>(
         &self,
         conn: &C,
-        required: ModifierSide,
+        step: KeyStep,
+        event: &KeyPressEvent,
     ) -> Result<bool> {
-        match required {
-            ModifierSide::Either => Ok(true), // Always match
-            ModifierSide::LeftOnly | ModifierSide::RightOnly => {
-                // Query X11 for all currently pressed keys (256 bits = 32 bytes)
-                let reply = conn.query_keymap()?.reply()?;
-                let keys = reply.keys;
-
-                // Check if Alt_L is pressed
-                let alt_l_pressed = self
-                    .alt_l_keycode
-                    .map(|kc| {
-                        let byte_idx = (kc / 8) as usize;
-                        let bit_idx = kc % 8;
-                        keys[byte_idx] & (1 << bit_idx) != 0
-                    })
-                    .unwrap_or(false);
-
-                // Check if Alt_R is pressed
-                let alt_r_pressed = self
-                    .alt_r_keycode
-                    .map(|kc| {
-                        let byte_idx = (kc / 8) as usize;
-                        let bit_idx = kc % 8;
-                        keys[byte_idx] & (1 << bit_idx) != 0
-                    })
-                    .unwrap_or(false);
-
-                #[cfg(debug_assertions)]
-                {
-                    use tracing::debug;
-                    debug!(
-                        "Alt state: L={}, R={}, required={:?}",
-                        alt_l_pressed, alt_r_pressed, required
-                    );
-                }
+        if self.modifier_map.relevant(event.state) != step.modifiers.bits()
+            || event.detail != step.keycode
+        {
+            return Ok(false);
+        }
+        if step.sides.is_either() {
+            return Ok(true);
+        }
 
-                Ok(match required {
-                    ModifierSide::LeftOnly => alt_l_pressed,
-                    ModifierSide::RightOnly => alt_r_pressed,
-                    ModifierSide::Either => unreachable!(),
-                })
+        // Without XInput 2 ask the server which keys are down (256 bits = 32 bytes)
+        let keys = match self.pressed_keys {
+            Some(keys) => keys,
+            None => conn.query_keymap()?.reply()?.keys,
+        };
+        Ok(self.sides_match(step.sides, &keys))
+    }
+
+    /// Checks the left/right requirements against the set of pressed keys
+    fn sides_match(&self, sides: ModifierSides, keys: &[u8; 32]) -> bool {
+        let is_pressed = |keysym: u32| {
+            self.get_keycode(keysym)
+                .is_ok_and(|kc| keys[usize::from(kc / 8)] & (1 << (kc % 8)) != 0)
+        };
+
+        sides
+            .with_keysyms()
+            .into_iter()
+            .all(|(side, left, right)| match side {
+                ModifierSide::Either => true,
+                ModifierSide::LeftOnly => is_pressed(left),
+                ModifierSide::RightOnly => is_pressed(right),
+            })
+    }
+
+    /// Starts tracking pressed keys from XInput 2 raw key events on the root window,
+    /// so left/right modifiers are checked without a round-trip on every key press
+    pub fn init_key_tracking<C: Connection>(&mut self, conn: &C, root_window: Window) {
+        let result = (|| -> Result<[u8; 32]> {
+            let version = conn.xinput_xi_query_version(2, 2)?.reply()?;
+            info!(
+                "XInput version {}.{}",
+                version.major_version, version.minor_version
+            );
+            conn.xinput_xi_select_events(
+                root_window,
+                &[xinput::EventMask {
+                    deviceid: xinput::Device::ALL_MASTER.into(),
+                    mask: vec![
+                        xinput::XIEventMask::RAW_KEY_PRESS | xinput::XIEventMask::RAW_KEY_RELEASE,
+                    ],
+                }],
+            )?
+            .check()?;
+            // Keys already held down when we start
+            Ok(conn.query_keymap()?.reply()?.keys)
+        })();
+
+        match result {
+            Ok(keys) => self.pressed_keys = Some(keys),
+            Err(e) => {
+                warn!(
+                    "XInput 2 unavailable, left/right modifiers use QueryKeymap: {}",
+                    e
+                );
+                self.pressed_keys = None;
             }
         }
     }
 
+    /// Records a raw key press or release from XInput 2
+    /// Returns the commands of a release binding the key just fired
+    pub fn handle_raw_key(&mut self, keycode: u32, pressed: bool) -> Option<&CommandChain> {
+        let keycode = u8::try_from(keycode).ok()?;
+        let keys = self.pressed_keys.as_mut()?;
+        let (byte, bit) = (usize::from(keycode / 8), keycode % 8);
+        if pressed {
+            keys[byte] |= 1 << bit;
+            self.last_pressed = Some(keycode);
+            return None;
+        }
+        keys[byte] &= !(1 << bit);
+
+        // Only a key released without pressing another one in between fires: Super_L alone,
+        // not the Super_L of Super_L+Return
+        let keys = *keys;
+        if self.last_pressed.take() != Some(keycode)
+            || self.active_mode.is_some()
+            || self.pending.is_some()
+            || self.hold.is_some()
+        {
+            return None;
+        }
+
+        let held = self.held_modifiers(&keys);
+        self.release_shortcuts
+            .iter()
+            .find(|shortcut| {
+                shortcut.keycode == keycode
+                    && shortcut.modifiers.bits() == held
+                    && self.sides_match(shortcut.sides, &keys)
+            })
+            .map(|shortcut| &shortcut.command)
+    }
+
+    /// Modifier bits set by the pressed keys, without the locks
+    fn held_modifiers(&self, keys: &[u8; 32]) -> u16 {
+        let state = self
+            .keycode_modifiers
+            .iter()
+            .filter(|&(&kc, _)| keys[usize::from(kc / 8)] & (1 << (kc % 8)) != 0)
+            .fold(0u16, |state, (_, modifier)| state | modifier.bits());
+        self.modifier_map.relevant(KeyButMask::from(state))
+    }
+
+    /// Grabs the keyboard while the modifiers of the binding that just ran stay held, so
+    /// their release can be seen (alt-tab). Returns false if none of them is held any more.
+    pub fn begin_hold<C: Connection>(&mut self, conn: &C, root_window: Window) -> Result<bool> {
+        if self.hold.is_some() {
+            return Ok(true);
+        }
+        // A mode already holds the keyboard, and ending the hold would release it
+        if self.active_mode.is_some() {
+            return Ok(false);
+        }
+
+        let keys = match self.pressed_keys {
+            Some(keys) => keys,
+            None => conn.query_keymap()?.reply()?.keys,
+        };
+        let held = self.held_modifiers(&keys) & self.last_modifiers;
+        if held == 0 {
+            return Ok(false);
+        }
+
+        let reply = conn
+            .grab_keyboard(
+                false,
+                root_window,
+                CURRENT_TIME,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )?
+            .reply()?;
+        if reply.status != GrabStatus::SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Could not grab the keyboard for a held binding: {:?}",
+                reply.status
+            ));
+        }
+        self.hold = Some(held);
+        Ok(true)
+    }
+
+    /// Returns true if this key release lets go of the last modifier keeping the hold
+    pub fn hold_released(&self, event: &KeyReleaseEvent) -> bool {
+        let Some(held) = self.hold else {
+            return false;
+        };
+        // The event state still has the released key's own modifier
+        let released = self
+            .keycode_modifiers
+            .get(&event.detail)
+            .map_or(0, |modifier| modifier.bits());
+        self.modifier_map.relevant(event.state) & !released & held == 0
+    }
+
+    /// Ends a held binding and releases the keyboard
+    pub fn end_hold<C: Connection>(&mut self, conn: &C) -> Result<()> {
+        if self.hold.take().is_some() {
+            conn.ungrab_keyboard(CURRENT_TIME)?;
+        }
+        Ok(())
+    }
+
     /// Parses a key combination string like "Super+t" or "Ctrl+Alt+Return"
-    /// Returns modifiers, keysym for the key, and Alt left/right requirement
-    fn parse_key_combination(&self, combo: &str) -> Result<(ModMask, u32, ModifierSide)> {
-        let parts: Vec<&str> = combo.split('+').collect();
+    /// Returns modifiers, keysym for the key, and left/right modifier requirements
+    fn parse_key_combination(&self, combo: &str) -> Result<(ModMask, Key, ModifierSides)> {
+        self.parse_combination(combo, false)
+    }
 
+    /// Parses a key combination; with `last_is_key` the last part is the key even if it
+    /// names a modifier, as in "release:Super_L"
+    fn parse_combination(
+        &self,
+        combo: &str,
+        last_is_key: bool,
+    ) -> Result<(ModMask, Key, ModifierSides)> {
+        let mut parts: Vec<&str> = combo.split('+').collect();
         if parts.is_empty() {
             return Err(anyhow::anyhow!("Empty key combination"));
         }
+        let last_key = if last_is_key { parts.pop() } else { None };
 
         // Build modifier bit flags by OR-ing each modifier together
         let mut modifiers = ModMask::from(0u16);
         let mut keyname = None;
-        let mut alt_side = ModifierSide::Either;
+        let mut sides = ModifierSides::default();
 
+        // Logical modifiers resolve to whichever ModN the server put them on
+        let map = &self.modifier_map;
         for part in parts {
             let part = part.trim();
             match part.to_lowercase().as_str() {
                 // Primary modifiers (see ADR-008 for why so many aliases)
-                "super" | "mod4" | "win" | "windows" | "cmd" => modifiers |= ModMask::M4,
-                "alt" | "mod1" | "meta" => {
-                    modifiers |= ModMask::M1;
-                    alt_side = ModifierSide::Either;
+                "super" | "win" | "windows" | "cmd" => modifiers |= map.super_key,
+                "alt" | "meta" => {
+                    modifiers |= map.alt;
+                    sides.alt = ModifierSide::Either;
                 }
                 "alt_l" => {
-                    modifiers |= ModMask::M1;
-                    alt_side = ModifierSide::LeftOnly;
+                    modifiers |= map.alt;
+                    sides.alt = ModifierSide::LeftOnly;
                 }
                 "alt_r" => {
-                    modifiers |= ModMask::M1;
-                    alt_side = ModifierSide::RightOnly;
+                    modifiers |= map.alt;
+                    sides.alt = ModifierSide::RightOnly;
                 }
                 "ctrl" | "control" | "ctl" => modifiers |= ModMask::CONTROL,
                 "shift" => modifiers |= ModMask::SHIFT,
 
-                // Less common modifiers
-                "mod2" | "numlock" | "num" => modifiers |= ModMask::M2,
-                "mod3" | "scrolllock" | "scroll" => modifiers |= ModMask::M3,
+                // Raw modifier bits, regardless of the modifier mapping
+                "mod1" => modifiers |= ModMask::M1,
+                "mod2" => modifiers |= ModMask::M2,
+                "mod3" => modifiers |= ModMask::M3,
+                "mod4" => modifiers |= ModMask::M4,
                 "mod5" => modifiers |= ModMask::M5,
 
-                // Special combination: all four main modifiers at once
-                "hyper" => {
-                    modifiers |= ModMask::M4 | ModMask::M1 | ModMask::CONTROL | ModMask::SHIFT
+                // Less common modifiers
+                "numlock" | "num" => modifiers |= map.num_lock,
+                "scrolllock" | "scroll" if is_empty(map.scroll_lock) => modifiers |= ModMask::M3,
+                "scrolllock" | "scroll" => modifiers |= map.scroll_lock,
+
+                // A real Hyper modifier if the keyboard has one,
+                // otherwise the special combination of all four main modifiers at once
+                "hyper" if !is_empty(map.hyper) => modifiers |= map.hyper,
+                "hyper" => modifiers |= map.super_key | map.alt | ModMask::CONTROL | ModMask::SHIFT,
+
+                // Left/right variants: "Super_R+Return" needs the right Super key
+                "super_l" | "super_r" => {
+                    modifiers |= map.super_key;
+                    sides.super_key = side_of(part);
+                }
+                "ctrl_l" | "ctrl_r" | "control_l" | "control_r" => {
+                    modifiers |= ModMask::CONTROL;
+                    sides.control = side_of(part);
+                }
+                "shift_l" | "shift_r" => {
+                    modifiers |= ModMask::SHIFT;
+                    sides.shift = side_of(part);
                 }
-                "super_l" | "super_r" => modifiers |= ModMask::M4,
-                "ctrl_l" | "ctrl_r" => modifiers |= ModMask::CONTROL,
-                "shift_l" | "shift_r" => modifiers |= ModMask::SHIFT,
 
                 _ => {
                     if keyname.is_some() {
@@ -347,31 +1380,98 @@ impl ShortcutManager {
             }
         }
 
+        if let Some(last) = last_key {
+            if keyname.is_some() {
+                return Err(anyhow::anyhow!("Multiple keys specified: {}", combo));
+            }
+            keyname = Some(last.trim());
+        }
+
         let keyname = keyname.ok_or_else(|| anyhow::anyhow!("No key specified in: {}", combo))?;
-        let keysym = self.get_keysym(keyname)?;
+        let key = self.get_key(keyname)?;
 
-        Ok((modifiers, keysym, alt_side))
+        Ok((modifiers, key, sides))
+    }
+
+    /// Gets the key for a given keyname: a keysym name, a raw `0x...` keysym or `code:<keycode>`
+    fn get_key(&self, keyname: &str) -> Result<Key> {
+        if let Some(code) = keyname.strip_prefix("code:") {
+            return code
+                .parse()
+                .map(Key::Code)
+                .map_err(|_| anyhow::anyhow!("Invalid keycode: {}", keyname));
+        }
+        if let Some(hex) = keyname.strip_prefix("0x") {
+            return u32::from_str_radix(hex, 16)
+                .map(Key::Sym)
+                .map_err(|_| anyhow::anyhow!("Invalid keysym: {}", keyname));
+        }
+        self.get_keysym(keyname).map(Key::Sym)
     }
 
     /// Gets the keysym for a given keyname
+    /// Names are case-sensitive, except that a single letter always means its (unshifted) key
+    /// and other names may be written in any case when that is unambiguous ("return", "escape")
     fn get_keysym(&self, keyname: &str) -> Result<u32> {
+        if keyname.len() == 1 && keyname.chars().all(|c| c.is_ascii_alphabetic()) {
+            return Ok(u32::from(keyname.as_bytes()[0].to_ascii_lowercase()));
+        }
+
         // Try exact match first
         if let Some(&keysym) = self.keyname_to_keysym.get(keyname) {
             return Ok(keysym);
         }
 
-        // Try lowercase
-        if let Some(&keysym) = self.keyname_to_keysym.get(&keyname.to_lowercase()) {
-            return Ok(keysym);
+        // Fall back to a case-insensitive match, unless X has several names differing in case
+        let lowercase = keyname.to_lowercase();
+        let matches: HashSet<u32> = self
+            .keyname_to_keysym
+            .iter()
+            .filter(|(name, _)| name.to_lowercase() == lowercase)
+            .map(|(_, &keysym)| keysym)
+            .collect();
+        match matches.len() {
+            1 => Ok(matches.into_iter().next().unwrap_or_default()),
+            0 => Err(anyhow::anyhow!("Unknown keyname: {}", keyname)),
+            _ => Err(anyhow::anyhow!(
+                "Ambiguous keyname {}: keysym names are case-sensitive",
+                keyname
+            )),
         }
+    }
 
-        Err(anyhow::anyhow!("Unknown keyname: {}", keyname))
+    /// Gets the keycode a key is bound to, and the modifiers needed to produce its keysym
+    /// Example: "exclam" on a US layout → (keycode 10, Shift)
+    fn resolve_key(&self, key: Key) -> Result<(u8, ModMask)> {
+        match key {
+            Key::Sym(keysym) => {
+                let position = self.get_key_position(keysym)?;
+                Ok((position.keycode, self.column_modifiers(position.column)))
+            }
+            Key::Code(keycode) => Ok((keycode, ModMask::from(0u16))),
+        }
+    }
+
+    /// Modifiers that must be held for a key to produce the keysym in `column`
+    fn column_modifiers(&self, column: usize) -> ModMask {
+        let level3 = self.modifier_map.level3;
+        match column {
+            1 => ModMask::SHIFT,
+            4 => level3,
+            5 => ModMask::SHIFT | level3,
+            _ => ModMask::from(0u16),
+        }
     }
 
     /// Gets the keycode for a given keysym
     /// Example: get_keycode(0x0071) → 24 (the 'q' key's keycode)
-    /// Returns error if keysym not found (e.g., modifier keysyms aren't stored)
     fn get_keycode(&self, keysym: u32) -> Result<u8> {
+        self.get_key_position(keysym)
+            .map(|position| position.keycode)
+    }
+
+    /// Gets where a keysym sits on the current keyboard layout
+    fn get_key_position(&self, keysym: u32) -> Result<KeyPosition> {
         self.keysym_to_keycode
             .get(&keysym)
             .copied()
@@ -379,6 +1479,61 @@ impl ShortcutManager {
     }
 }
 
+/// Keysym columns that can be reached with modifiers alone (2 and 3 need a group switch)
+const BINDABLE_COLUMNS: [usize; 4] = [0, 1, 4, 5];
+
+/// Builds the reverse keyboard map: keysym → the keycode producing it with the fewest modifiers
+/// Each physical key can produce multiple symbols depending on the modifiers held
+/// Example: keycode 38 → [0x0061 ('a'), 0x0041 ('A'), 0x00e1 ('á'), 0x00c1 ('Á')]
+fn build_keycode_map(
+    min_keycode: u8,
+    keysyms_per_keycode: usize,
+    keysyms: &[u32],
+) -> HashMap<u32, KeyPosition> {
+    let mut keysym_to_keycode: HashMap<u32, KeyPosition> = HashMap::new();
+    if keysyms_per_keycode == 0 {
+        return keysym_to_keycode;
+    }
+
+    for (index, chunk) in keysyms.chunks(keysyms_per_keycode).enumerate() {
+        // Calculate the actual keycode for this chunk
+        let keycode = min_keycode.saturating_add(index as u8);
+
+        for column in BINDABLE_COLUMNS {
+            let Some(&keysym) = chunk.get(column) else {
+                continue;
+            };
+            if keysym == 0 {
+                continue;
+            }
+
+            // Keep the earliest column so "a" stays unshifted even if another key has it shifted
+            let position = KeyPosition { keycode, column };
+            keysym_to_keycode
+                .entry(keysym)
+                .and_modify(|existing| {
+                    if column < existing.column {
+                        *existing = position;
+                    }
+                })
+                .or_insert(position);
+        }
+    }
+    keysym_to_keycode
+}
+
+/// Splits "Super+w h" into its steps; spaces around `+` stay within a step
+fn split_sequence(key_combo: &str) -> Vec<String> {
+    let mut steps: Vec<String> = Vec::new();
+    for token in key_combo.split_whitespace() {
+        match steps.last_mut() {
+            Some(last) if last.ends_with('+') || token.starts_with('+') => last.push_str(token),
+            _ => steps.push(token.to_string()),
+        }
+    }
+    steps
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -389,11 +1544,32 @@ mod tests {
             keyname_to_keysym: ShortcutManager::build_keysym_table(),
             keysym_to_keycode: HashMap::new(),
             shortcuts: Vec::new(),
-            alt_l_keycode: None,
-            alt_r_keycode: None,
+            modes: HashMap::new(),
+            active_mode: None,
+            sequences: Vec::new(),
+            release_shortcuts: Vec::new(),
+            last_pressed: None,
+            last_modifiers: 0,
+            hold: None,
+            pending: None,
+            sequence_timeout: Duration::from_millis(1000),
+            modifier_keycodes: HashSet::new(),
+            modifier_map: ModifierMap::default(),
+            keycode_modifiers: HashMap::new(),
+            invalid_shortcuts: Vec::new(),
+            invalid_mode_shortcuts: Vec::new(),
+            grab_failures: Vec::new(),
+            pressed_keys: None,
         }
     }
 
+    // Places a keysym unshifted on a keycode
+    fn map_key(manager: &mut ShortcutManager, keysym: u32, keycode: u8) {
+        manager
+            .keysym_to_keycode
+            .insert(keysym, KeyPosition { keycode, column: 0 });
+    }
+
     // ==================== Key Parsing Tests ====================
 
     #[test]
@@ -401,7 +1577,7 @@ mod tests {
         let manager = create_test_manager();
         let (modifiers, keysym, _) = manager.parse_key_combination("t").unwrap();
         assert_eq!(modifiers, ModMask::from(0u16));
-        assert_eq!(keysym, 't' as u32);
+        assert_eq!(keysym, Key::Sym('t' as u32));
     }
 
     #[test]
@@ -409,7 +1585,7 @@ mod tests {
         let manager = create_test_manager();
         let (modifiers, keysym, _) = manager.parse_key_combination("Super+t").unwrap();
         assert_eq!(modifiers, ModMask::M4);
-        assert_eq!(keysym, 't' as u32);
+        assert_eq!(keysym, Key::Sym('t' as u32));
     }
 
     #[test]
@@ -417,7 +1593,7 @@ mod tests {
         let manager = create_test_manager();
         let (modifiers, keysym, _) = manager.parse_key_combination("Ctrl+Alt+Return").unwrap();
         assert_eq!(modifiers, ModMask::CONTROL | ModMask::M1);
-        assert_eq!(keysym, 0xff0d);
+        assert_eq!(keysym, Key::Sym(0xff0d));
     }
 
     #[test]
@@ -425,7 +1601,152 @@ mod tests {
         let manager = create_test_manager();
         let (modifiers, keysym, _) = manager.parse_key_combination("F1").unwrap();
         assert_eq!(modifiers, ModMask::from(0u16));
-        assert_eq!(keysym, 0xffbe);
+        assert_eq!(keysym, Key::Sym(0xffbe));
+    }
+
+    #[test]
+    fn test_parse_any_keysym_name() {
+        let manager = create_test_manager();
+        let keysym = |name: &str| manager.parse_key_combination(name).unwrap().1;
+
+        assert_eq!(keysym("comma"), Key::Sym(0x2c));
+        assert_eq!(keysym("bracketleft"), Key::Sym(0x5b));
+        assert_eq!(keysym("grave"), Key::Sym(0x60));
+        assert_eq!(keysym("F24"), Key::Sym(0xffd5));
+        assert_eq!(keysym("KP_Enter"), Key::Sym(0xff8d));
+        assert_eq!(keysym("XF86AudioRaiseVolume"), Key::Sym(0x1008ff13));
+        assert_eq!(keysym("XF86MonBrightnessUp"), Key::Sym(0x1008ff02));
+
+        // Letters always name their key; other names are case-insensitive unless ambiguous
+        assert_eq!(keysym("Super+T"), Key::Sym('t' as u32));
+        assert_eq!(keysym("return"), Key::Sym(0xff0d));
+        assert_eq!(keysym("Aacute"), Key::Sym(0xc1));
+        assert_eq!(keysym("aacute"), Key::Sym(0xe1));
+        assert!(manager.parse_key_combination("AACUTE").is_err());
+    }
+
+    #[test]
+    fn test_parse_raw_keysym_and_keycode() {
+        let manager = create_test_manager();
+        let (modifiers, key, _) = manager.parse_key_combination("Super+0x1008ff11").unwrap();
+        assert_eq!(modifiers, ModMask::M4);
+        assert_eq!(key, Key::Sym(0x1008ff11));
+
+        let (_, key, _) = manager.parse_key_combination("Alt+code:123").unwrap();
+        assert_eq!(key, Key::Code(123));
+        assert_eq!(
+            manager.resolve_key(key).unwrap(),
+            (123, ModMask::from(0u16))
+        );
+
+        assert!(manager.parse_key_combination("code:300").is_err());
+        assert!(manager.parse_key_combination("0xzz").is_err());
+    }
+
+    #[test]
+    fn test_keycode_map_uses_all_columns() {
+        // keycode 10: 1 ! (group 2 ignored) ¹ ¡   keycode 11: 2 @
+        let keysyms = [
+            0x31, 0x21, 0x31, 0x21, 0xb9, 0xa1, //
+            0x32, 0x40, 0x32, 0x40, 0, 0,
+        ];
+        let map = build_keycode_map(10, 6, &keysyms);
+
+        assert_eq!(
+            map[&0x31],
+            KeyPosition {
+                keycode: 10,
+                column: 0
+            }
+        );
+        assert_eq!(
+            map[&0x21],
+            KeyPosition {
+                keycode: 10,
+                column: 1
+            }
+        );
+        assert_eq!(
+            map[&0xb9],
+            KeyPosition {
+                keycode: 10,
+                column: 4
+            }
+        );
+        assert_eq!(
+            map[&0xa1],
+            KeyPosition {
+                keycode: 10,
+                column: 5
+            }
+        );
+        assert_eq!(
+            map[&0x40],
+            KeyPosition {
+                keycode: 11,
+                column: 1
+            }
+        );
+        assert!(!map.contains_key(&0));
+
+        // Shifted symbols are bound with the modifiers that produce them
+        let mut manager = create_test_manager();
+        manager.keysym_to_keycode = map;
+        let steps = manager.parse_sequence("Super+exclam").unwrap();
+        assert_eq!(steps[0].modifiers, ModMask::M4 | ModMask::SHIFT);
+        assert_eq!(steps[0].keycode, 10);
+        let steps = manager.parse_sequence("exclamdown").unwrap();
+        assert_eq!(steps[0].modifiers, ModMask::SHIFT | ModMask::M5);
+    }
+
+    #[test]
+    fn test_modifier_map_from_server_mapping() {
+        // Num_Lock=77 on Mod2, Super_L=133 on Mod3, Hyper_L=207 on Mod4, Alt_L=64 on Mod1
+        let keycodes_of = HashMap::from([(0xff7f, 77), (0xffeb, 133), (0xffed, 207), (0xffe9, 64)]);
+        let rows = [
+            [50, 62],  // Shift
+            [66, 0],   // Lock
+            [37, 105], // Control
+            [64, 0],   // Mod1
+            [77, 0],   // Mod2
+            [133, 0],  // Mod3
+            [207, 0],  // Mod4
+            [0, 0],    // Mod5
+        ];
+        let keycodes: Vec<u8> = rows.concat();
+        let map =
+            ModifierMap::from_mapping(&keycodes, 2, |keysym| keycodes_of.get(&keysym).copied());
+
+        assert_eq!(map.alt, ModMask::M1);
+        assert_eq!(map.super_key, ModMask::M3);
+        assert_eq!(map.hyper, ModMask::M4);
+        assert_eq!(map.num_lock, ModMask::M2);
+        assert!(is_empty(map.scroll_lock));
+        assert_eq!(map.level3, ModMask::M5); // not mapped, default kept
+
+        let mut manager = create_test_manager();
+        manager.modifier_map = map;
+        let (modifiers, _, _) = manager.parse_key_combination("Super+t").unwrap();
+        assert_eq!(modifiers, ModMask::M3);
+        let (modifiers, _, _) = manager.parse_key_combination("Hyper+t").unwrap();
+        assert_eq!(modifiers, ModMask::M4);
+        let (modifiers, _, _) = manager.parse_key_combination("Mod4+t").unwrap();
+        assert_eq!(modifiers, ModMask::M4);
+    }
+
+    #[test]
+    fn test_lock_combinations_and_matching() {
+        let map = ModifierMap::default();
+        let combinations = map.lock_combinations();
+        assert_eq!(combinations.len(), 4);
+        assert!(combinations.contains(&ModMask::from(0u16)));
+        assert!(combinations.contains(&(ModMask::LOCK | ModMask::M2)));
+
+        // CapsLock and NumLock are ignored when matching, like they are grabbed
+        let state = KeyButMask::MOD4 | KeyButMask::LOCK | KeyButMask::MOD2;
+        assert_eq!(map.relevant(state), ModMask::M4.bits());
+        let state = KeyButMask::MOD4 | KeyButMask::MOD3;
+        assert_eq!(map.relevant(state), (ModMask::M4 | ModMask::M3).bits());
     }
 
     #[test]
@@ -439,7 +1760,7 @@ mod tests {
         let manager = create_test_manager();
         let (modifiers, keysym, _) = manager.parse_key_combination("Mod2+t").unwrap();
         assert_eq!(modifiers, ModMask::M2);
-        assert_eq!(keysym, 't' as u32);
+        assert_eq!(keysym, Key::Sym('t' as u32));
     }
 
     #[test]
@@ -447,7 +1768,7 @@ mod tests {
         let manager = create_test_manager();
         let (modifiers, keysym, _) = manager.parse_key_combination("NumLock+Return").unwrap();
         assert_eq!(modifiers, ModMask::M2);
-        assert_eq!(keysym, 0xff0d);
+        assert_eq!(keysym, Key::Sym(0xff0d));
     }
 
     #[test]
@@ -456,7 +1777,7 @@ mod tests {
         let (modifiers, keysym, _) = manager.parse_key_combination("Hyper+space").unwrap();
         let expected = ModMask::M4 | ModMask::M1 | ModMask::CONTROL | ModMask::SHIFT;
         assert_eq!(modifiers, expected);
-        assert_eq!(keysym, 0x0020);
+        assert_eq!(keysym, Key::Sym(0x0020));
     }
 
     #[test]
@@ -498,28 +1819,67 @@ mod tests {
         let manager = create_test_manager();
 
         // Alt_L should return LeftOnly
-        let (modifiers, keysym, alt_side) = manager.parse_key_combination("Alt_L+t").unwrap();
+        let (modifiers, keysym, sides) = manager.parse_key_combination("Alt_L+t").unwrap();
         assert_eq!(modifiers, ModMask::M1);
-        assert_eq!(keysym, 't' as u32);
-        assert_eq!(alt_side, ModifierSide::LeftOnly);
+        assert_eq!(keysym, Key::Sym('t' as u32));
+        assert_eq!(sides.alt, ModifierSide::LeftOnly);
 
         // Alt_R should return RightOnly
-        let (modifiers, keysym, alt_side) = manager.parse_key_combination("Alt_R+t").unwrap();
+        let (modifiers, keysym, sides) = manager.parse_key_combination("Alt_R+t").unwrap();
         assert_eq!(modifiers, ModMask::M1);
-        assert_eq!(keysym, 't' as u32);
-        assert_eq!(alt_side, ModifierSide::RightOnly);
+        assert_eq!(keysym, Key::Sym('t' as u32));
+        assert_eq!(sides.alt, ModifierSide::RightOnly);
 
         // Alt should return Either
-        let (modifiers, keysym, alt_side) = manager.parse_key_combination("Alt+t").unwrap();
+        let (modifiers, keysym, sides) = manager.parse_key_combination("Alt+t").unwrap();
         assert_eq!(modifiers, ModMask::M1);
-        assert_eq!(keysym, 't' as u32);
-        assert_eq!(alt_side, ModifierSide::Either);
+        assert_eq!(keysym, Key::Sym('t' as u32));
+        assert_eq!(sides.alt, ModifierSide::Either);
 
         // Non-Alt modifiers should default to Either
-        let (modifiers, keysym, alt_side) = manager.parse_key_combination("Ctrl+c").unwrap();
+        let (modifiers, keysym, sides) = manager.parse_key_combination("Ctrl+c").unwrap();
         assert_eq!(modifiers, ModMask::CONTROL);
-        assert_eq!(keysym, 'c' as u32);
-        assert_eq!(alt_side, ModifierSide::Either);
+        assert_eq!(keysym, Key::Sym('c' as u32));
+        assert_eq!(sides.alt, ModifierSide::Either);
+    }
+
+    #[test]
+    fn test_left_right_sides_for_all_modifiers() {
+        let manager = create_test_manager();
+
+        let (modifiers, _, sides) = manager.parse_key_combination("Super_R+Return").unwrap();
+        assert_eq!(modifiers, ModMask::M4);
+        assert_eq!(sides.super_key, ModifierSide::RightOnly);
+        assert_eq!(sides.alt, ModifierSide::Either);
+
+        let (modifiers, _, sides) = manager.parse_key_combination("Ctrl_L+Shift_R+t").unwrap();
+        assert_eq!(modifiers, ModMask::CONTROL | ModMask::SHIFT);
+        assert_eq!(sides.control, ModifierSide::LeftOnly);
+        assert_eq!(sides.shift, ModifierSide::RightOnly);
+        assert_eq!(sides.super_key, ModifierSide::Either);
+    }
+
+    #[test]
+    fn test_sides_match_tracked_keys() {
+        let mut manager = create_test_manager();
+        map_key(&mut manager, 0xffeb, 133); // Super_L
+        map_key(&mut manager, 0xffec, 134); // Super_R
+        manager.pressed_keys = Some([0; 32]);
+
+        let (_, _, right) = manager.parse_key_combination("Super_R+Return").unwrap();
+        let (_, _, left) = manager.parse_key_combination("Super_L+Return").unwrap();
+
+        manager.handle_raw_key(134, true);
+        let keys = manager.pressed_keys.unwrap();
+        assert!(manager.sides_match(right, &keys));
+        assert!(!manager.sides_match(left, &keys));
+        assert!(manager.sides_match(ModifierSides::default(), &keys));
+
+        manager.handle_raw_key(134, false);
+        manager.handle_raw_key(133, true);
+        let keys = manager.pressed_keys.unwrap();
+        assert!(!manager.sides_match(right, &keys));
+        assert!(manager.sides_match(left, &keys));
     }
 
     #[test]
@@ -532,7 +1892,7 @@ mod tests {
             .unwrap();
         let expected = ModMask::CONTROL | ModMask::M1 | ModMask::SHIFT;
         assert_eq!(modifiers, expected);
-        assert_eq!(keysym, 0xffff); // Delete key
+        assert_eq!(keysym, Key::Sym(0xffff)); // Delete key
 
         // Test quadruple modifier
         let (modifiers, keysym, _) = manager
@@ -540,7 +1900,7 @@ mod tests {
             .unwrap();
         let expected = ModMask::M4 | ModMask::CONTROL | ModMask::M1 | ModMask::SHIFT;
         assert_eq!(modifiers, expected);
-        assert_eq!(keysym, 0xffc9); // F12 key
+        assert_eq!(keysym, Key::Sym(0xffc9)); // F12 key
     }
 
     #[test]
@@ -563,15 +1923,196 @@ mod tests {
     #[test]
     fn test_shortcut_creation() {
         let shortcut = Shortcut {
+            combo: "Super+Return".to_string(),
             modifiers: ModMask::M4,
             keycode: 28,
-            command: "xterm".to_string(),
-            alt_side: ModifierSide::Either,
+            command: CommandChain::parse("exec xterm").unwrap(),
+            sides: ModifierSides::default(),
         };
 
         assert_eq!(shortcut.modifiers, ModMask::M4);
         assert_eq!(shortcut.keycode, 28);
-        assert_eq!(shortcut.command, "xterm");
+        assert_eq!(shortcut.command, CommandChain::parse("exec xterm").unwrap());
+    }
+
+    #[test]
+    fn test_register_modes_adds_escape() {
+        let mut manager = create_test_manager();
+        map_key(&mut manager, 'h' as u32, 43);
+        map_key(&mut manager, ESCAPE_KEYSYM, 9);
+
+        let mut resize = ModeConfig::default();
+        resize
+            .shortcuts
+            .insert("h".to_string(), "resize left 20".to_string());
+        let mut custom_escape = ModeConfig::default();
+        custom_escape
+            .shortcuts
+            .insert("Escape".to_string(), "mode resize".to_string());
+        let modes = HashMap::from([
+            ("resize".to_string(), resize),
+            ("custom".to_string(), custom_escape),
+        ]);
+        manager.register_modes(&modes);
+
+        let resize = &manager.modes["resize"];
+        assert_eq!(resize.len(), 2);
+        let escape = resize.iter().find(|s| s.keycode == 9).unwrap();
+        assert_eq!(escape.command.to_string(), "mode default");
+
+        // A mode's own Escape binding wins over the implicit one
+        let custom = &manager.modes["custom"];
+        assert_eq!(custom.len(), 1);
+        assert_eq!(custom[0].command.to_string(), "mode resize");
+
+        assert_eq!(manager.active_mode(), DEFAULT_MODE);
+    }
+
+    #[test]
+    fn test_conflicts_between_shortcuts() {
+        let mut manager = create_test_manager();
+        map_key(&mut manager, 'f' as u32, 41);
+        map_key(&mut manager, 'w' as u32, 25);
+        map_key(&mut manager, 'j' as u32, 44);
+        map_key(&mut manager, 'x' as u32, 53);
+
+        let shortcuts = HashMap::from(
+            [
+                ("Alt+f", "focus_next"),
+                ("alt+F", "focus_prev"),
+                ("Alt_L+x", "toggle_zoom"),
+                ("Alt+x", "toggle_fullscreen"),
+                ("Alt_R+x", "rotate_windows"),
+                ("Super+w", "focus_next"),
+                ("Super+w j", "focus_prev"),
+                ("Super+j", "switch_workspace_next"),
+            ]
+            .map(|(key, command)| (key.to_string(), command.to_string())),
+        );
+        manager.load_shortcuts(&shortcuts);
+
+        let summary = manager.summary();
+        let conflicts: Vec<_> = summary
+            .conflicts
+            .iter()
+            .map(|c| (c.first.as_str(), c.second.as_str(), c.kind))
+            .collect();
+        assert_eq!(
+            conflicts,
+            [
+                ("Alt+f", "alt+F", ConflictKind::Duplicate),
+                ("Alt+x", "Alt_L+x", ConflictKind::Overlap),
+                ("Alt+x", "Alt_R+x", ConflictKind::Overlap),
+                ("Super+w", "Super+w j", ConflictKind::Prefix),
+            ]
+        );
+        assert!(summary.has_errors());
+        assert_eq!(summary.problems().len(), 4);
+    }
+
+    #[test]
+    fn test_summary_reports_invalid_bindings() {
+        let mut manager = create_test_manager();
+        map_key(&mut manager, 'h' as u32, 43);
+
+        let shortcuts = HashMap::from([
+            ("Super+h".to_string(), "focus_next".to_string()),
+            ("Super+nosuchkey".to_string(), "focus_prev".to_string()),
+        ]);
+        manager.load_shortcuts(&shortcuts);
+
+        let summary = manager.summary();
+        assert_eq!(summary.shortcuts, 1);
+        assert_eq!(summary.invalid.len(), 1);
+        assert!(summary.invalid[0].starts_with("Super+nosuchkey: "));
+        assert!(summary.conflicts.is_empty());
+        assert!(summary.has_errors());
+
+        manager.load_shortcuts(&HashMap::new());
+        assert!(!manager.summary().has_errors());
+    }
+
+    #[test]
+    fn test_release_bindings() {
+        let mut manager = create_test_manager();
+        map_key(&mut manager, 0xffeb, 133); // Super_L
+        map_key(&mut manager, 0xff0d, 36); // Return
+        manager.keycode_modifiers.insert(133, ModMask::M4);
+        manager.pressed_keys = Some([0; 32]);
+
+        let shortcuts = HashMap::from([(
+            "release:Super_L".to_string(),
+            "exec rofi -show run".to_string(),
+        )]);
+        manager.load_shortcuts(&shortcuts);
+        assert_eq!(manager.invalid_shortcuts, Vec::<String>::new());
+        assert_eq!(manager.release_shortcuts.len(), 1);
+        assert_eq!(manager.release_shortcuts[0].keycode, 133);
+        assert_eq!(manager.release_shortcuts[0].modifiers, ModMask::from(0u16));
+
+        // Tapping Super_L fires
+        assert!(manager.handle_raw_key(133, true).is_none());
+        let chain = manager.handle_raw_key(133, false).unwrap();
+        assert_eq!(chain.to_string(), "exec rofi -show run");
+
+        // Super_L+Return does not
+        manager.handle_raw_key(133, true);
+        manager.handle_raw_key(36, true);
+        manager.handle_raw_key(36, false);
+        assert!(manager.handle_raw_key(133, false).is_none());
+    }
+
+    #[test]
+    fn test_hold_released() {
+        let mut manager = create_test_manager();
+        manager.keycode_modifiers.insert(64, ModMask::M1); // Alt_L
+        manager.hold = Some(ModMask::M1.bits());
+
+        let release = |detail: u8, state: KeyButMask| KeyReleaseEvent {
+            detail,
+            state,
+            ..Default::default()
+        };
+        // Releasing Tab with Alt still down keeps cycling
+        assert!(!manager.hold_released(&release(23, KeyButMask::MOD1)));
+        // Releasing Alt (the state still has it) ends the hold
+        assert!(manager.hold_released(&release(64, KeyButMask::MOD1)));
+    }
+
+    #[test]
+    fn test_split_sequence() {
+        assert_eq!(split_sequence("Super+w h"), ["Super+w", "h"]);
+        assert_eq!(split_sequence("Super + w  Shift+3"), ["Super+w", "Shift+3"]);
+        assert_eq!(split_sequence("Alt+j"), ["Alt+j"]);
+        assert!(split_sequence("  ").is_empty());
+    }
+
+    #[test]
+    fn test_parse_sequence() {
+        let mut manager = create_test_manager();
+        map_key(&mut manager, 'w' as u32, 25);
+        map_key(&mut manager, 'h' as u32, 43);
+
+        let steps = manager.parse_sequence("Super+w Shift+h").unwrap();
+        assert_eq!(
+            steps,
+            [
+                KeyStep {
+                    modifiers: ModMask::M4,
+                    keycode: 25,
+                    sides: ModifierSides::default(),
+                },
+                KeyStep {
+                    modifiers: ModMask::SHIFT,
+                    keycode: 43,
+                    sides: ModifierSides::default(),
+                },
+            ]
+        );
+
+        // Modes grab the whole keyboard already, so they only take single keys
+        assert!(manager.parse_shortcut("Super+w h", "focus_next").is_err());
+        assert!(manager.parse_shortcut("h", "focus_next").is_ok());
     }
 
     // Note: handle_key_press now requires X11 Connection, so integration testing
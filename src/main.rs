@@ -1,25 +1,144 @@
 //! Rustile - X11 tiling window manager entry point
 
 use anyhow::Result;
-use tracing::info;
+use std::os::fd::AsFd;
+use std::panic::{self, AssertUnwindSafe};
+use tracing::{error, info};
+use x11rb::connection::Connection;
 
 // Module declarations
 mod bsp;
+mod command;
 mod config;
+mod events;
+mod ewmh;
+mod ipc;
 mod keyboard;
+mod rules;
+mod session;
+mod signals;
+mod socket_path;
+mod spawn;
 mod window_manager;
 mod workspace;
 mod workspace_renderer;
 
+use config::Config;
+use keyboard::ShortcutManager;
 use window_manager::WindowManager;
 
+/// Checks the config and its key bindings, then exits without starting the window manager
+const CHECK_CONFIG_FLAG: &str = "--check-config";
+
+// Windows are handed back by catching the unwind; an aborting panic would leave them unmapped
+#[cfg(panic = "abort")]
+compile_error!("rustile must be built with panic = \"unwind\" so windows are restored on panic");
+
 fn main() -> Result<()> {
+    if std::env::args().skip(1).any(|arg| arg == CHECK_CONFIG_FLAG) {
+        return check_config();
+    }
+
     tracing_subscriber::fmt::init();
     info!("Starting Rustile window manager");
 
+    // Log panics through tracing too, keeping the default message and backtrace hint
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        error!("rustile panicked: {}", info);
+        default_hook(info);
+    }));
+
     let (conn, screen_num) = x11rb::connect(None)?;
     info!("Connected to X11 display on screen {}", screen_num);
 
-    let wm = WindowManager::new(conn, screen_num)?;
-    wm.run()
+    // Duplicate the connection fd so the event loop can poll it alongside the IPC socket
+    let x11_fd = conn.stream().as_fd().try_clone_to_owned()?;
+
+    // Nothing is unmapped before this point, so a failure here leaves no windows behind
+    let mut wm = WindowManager::new(conn, screen_num)?;
+
+    with_teardown(
+        &mut wm,
+        |wm| {
+            // After an in-place restart, pick up the previous process's workspaces
+            let restore_path = session::restore_path_from_args(std::env::args().skip(1));
+            if let Some(path) = &restore_path
+                && let Err(e) = wm.restore_session(path)
+            {
+                error!("Failed to restore state from {:?}: {}", path, e);
+            }
+
+            // Session programs are still running after a restart, only exec_always runs again
+            wm.autostart(restore_path.is_none());
+
+            wm.run(x11_fd)
+        },
+        WindowManager::teardown,
+    )
+}
+
+/// Runs `body`, then `teardown` whether it returned or panicked, so windows are always handed back
+fn with_teardown<S>(
+    state: &mut S,
+    body: impl FnOnce(&mut S) -> Result<()>,
+    teardown: impl FnOnce(&mut S),
+) -> Result<()> {
+    let result = panic::catch_unwind(AssertUnwindSafe(|| body(state)));
+    teardown(state);
+
+    match result {
+        Ok(result) => result,
+        Err(_) => Err(anyhow::anyhow!("rustile panicked, windows were restored")),
+    }
+}
+
+/// Validates the config file and reports binding problems; exits with 1 if any binding can't fire
+fn check_config() -> Result<()> {
+    let config = Config::load()?;
+    println!("{:?}: OK", Config::config_path()?);
+
+    // Key names only resolve against a keyboard mapping, which needs the X server
+    let (conn, _) = match x11rb::connect(None) {
+        Ok(connection) => connection,
+        Err(e) => {
+            println!("Key bindings not checked, no X display: {}", e);
+            return Ok(());
+        }
+    };
+    let mut shortcut_manager = ShortcutManager::new(&conn, conn.setup())?;
+    shortcut_manager.load_shortcuts(config.shortcuts());
+    shortcut_manager.register_modes(&config.modes);
+
+    let summary = shortcut_manager.summary();
+    println!("Key bindings: {}", summary);
+    for problem in summary.problems() {
+        println!("  {}", problem);
+    }
+    if summary.has_errors() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_teardown_runs_after_panic() {
+        let mut torn_down = false;
+        let result = with_teardown(
+            &mut torn_down,
+            |_| panic!("simulated crash in the event loop"),
+            |torn_down| *torn_down = true,
+        );
+        assert!(torn_down);
+        assert!(result.unwrap_err().to_string().contains("panicked"));
+
+        let mut torn_down = false;
+        let result = with_teardown(&mut torn_down, |_| Ok(()), |torn_down| *torn_down = true);
+        assert!(torn_down);
+        assert!(result.is_ok());
+    }
 }
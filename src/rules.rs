@@ -0,0 +1,491 @@
+//! Window rules: per-application placement and behaviour, decided when a window is mapped
+
+use anyhow::Result;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use x11rb::protocol::xproto::Window;
+
+use crate::bsp::Direction;
+use crate::command::WorkspaceTarget;
+use crate::config::{Validate, validators};
+use crate::workspace_renderer::WindowGeometry;
+
+/// Window types a rule can match: `_NET_WM_WINDOW_TYPE_<NAME>` in lowercase
+pub const WINDOW_TYPES: &[&str] = &[
+    "normal",
+    "dialog",
+    "utility",
+    "toolbar",
+    "splash",
+    "menu",
+    "dropdown_menu",
+    "popup_menu",
+    "tooltip",
+    "notification",
+    "combo",
+    "dnd",
+    "dock",
+    "desktop",
+];
+
+/// Properties of a window that rules match on
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowInfo {
+    /// Second part of `WM_CLASS`, e.g. "Firefox"
+    pub class: String,
+    /// First part of `WM_CLASS`, e.g. "Navigator"
+    pub instance: String,
+    /// `_NET_WM_NAME`, or `WM_NAME` if the client has no UTF-8 title
+    pub title: String,
+    /// `WM_WINDOW_ROLE`, e.g. "pop-up"
+    pub role: String,
+    /// Preferred `_NET_WM_WINDOW_TYPE` as listed in `WINDOW_TYPES`
+    pub window_type: String,
+}
+
+impl WindowInfo {
+    /// Returns true if either part of `WM_CLASS` is `name`, as scratchpads look windows up
+    pub fn has_class(&self, name: &str) -> bool {
+        self.class == name || self.instance == name
+    }
+}
+
+/// A string matcher: a glob using `*` and `?`, or a regex between slashes like `/^Fire(fox|bird)$/`
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    source: String,
+    regex: Regex,
+}
+
+impl Pattern {
+    /// Compiles a glob or `/regex/`; globs must match the whole value
+    pub fn new(source: &str) -> Result<Self> {
+        let regex = match source.strip_prefix('/').and_then(|s| s.strip_suffix('/')) {
+            Some(regex) => Regex::new(regex)
+                .map_err(|e| anyhow::anyhow!("Invalid regex {:?}: {}", source, e))?,
+            None => {
+                let mut regex = String::from("^");
+                for c in source.chars() {
+                    match c {
+                        '*' => regex.push_str(".*"),
+                        '?' => regex.push('.'),
+                        c => regex.push_str(&regex::escape(&c.to_string())),
+                    }
+                }
+                regex.push('$');
+                Regex::new(&regex)?
+            }
+        };
+        Ok(Self {
+            source: source.to_string(),
+            regex,
+        })
+    }
+
+    /// Checks if a property value matches
+    pub fn matches(&self, value: &str) -> bool {
+        self.regex.is_match(value)
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Size and optional position of a floating window, in X geometry syntax:
+/// `800x600` (centered) or `800x600+100+50`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub width: u32,
+    pub height: u32,
+    pub position: Option<(i32, i32)>,
+}
+
+impl Geometry {
+    /// Parses `WIDTHxHEIGHT` with an optional `+X+Y`
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid geometry {:?}, expected WxH or WxH+X+Y", text);
+        let (size, position) = match text.split_once('+') {
+            Some((size, position)) => (size, Some(position)),
+            None => (text, None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let width: u32 = width.parse().map_err(|_| invalid())?;
+        let height: u32 = height.parse().map_err(|_| invalid())?;
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        let position = match position {
+            Some(position) => {
+                let (x, y) = position.split_once('+').ok_or_else(invalid)?;
+                Some((
+                    x.parse().map_err(|_| invalid())?,
+                    y.parse().map_err(|_| invalid())?,
+                ))
+            }
+            None => None,
+        };
+        Ok(Self {
+            width,
+            height,
+            position,
+        })
+    }
+
+    /// Places a window on the screen, centering it if no position was given
+    pub fn place(&self, window: Window, screen_width: u16, screen_height: u16) -> WindowGeometry {
+        let (x, y) = self.position.unwrap_or_else(|| {
+            (
+                (i32::from(screen_width) - self.width as i32) / 2,
+                (i32::from(screen_height) - self.height as i32) / 2,
+            )
+        });
+        WindowGeometry {
+            window,
+            x,
+            y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)?;
+        if let Some((x, y)) = self.position {
+            write!(f, "+{x}+{y}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for Geometry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Geometry {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Geometry::parse(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Workspace a rule sends windows to: an index as in `get_workspaces`, or a name
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum RuleWorkspace {
+    Index(usize),
+    Name(String),
+}
+
+impl RuleWorkspace {
+    /// Converts to the target used by workspace commands
+    pub fn target(&self) -> WorkspaceTarget {
+        match self {
+            RuleWorkspace::Index(index) => WorkspaceTarget::Index(*index),
+            RuleWorkspace::Name(name) => WorkspaceTarget::Name(name.clone()),
+        }
+    }
+}
+
+/// A `[[rules]]` entry: when every given matcher matches a new window, the actions apply
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowRule {
+    /// `WM_CLASS` class
+    pub class: Option<Pattern>,
+    /// `WM_CLASS` instance
+    pub instance: Option<Pattern>,
+    /// Window title
+    pub title: Option<Pattern>,
+    /// `WM_WINDOW_ROLE`
+    pub role: Option<Pattern>,
+    /// `_NET_WM_WINDOW_TYPE`, one of `WINDOW_TYPES`
+    pub window_type: Option<String>,
+
+    /// Workspace to open on; named workspaces are created if needed
+    pub workspace: Option<RuleWorkspace>,
+    /// Float above the tiled windows instead of joining the layout
+    pub floating: Option<bool>,
+    /// Floating size and position (defaults to the window's own size, centered)
+    pub geometry: Option<Geometry>,
+    /// Start in fullscreen
+    pub fullscreen: Option<bool>,
+    /// Split ratio of the split the window creates, like `layout.bsp_split_ratio`
+    pub split_ratio: Option<f32>,
+    /// Side of the focused window the new window goes to: left, right, up or down
+    pub insert: Option<Direction>,
+    /// Always (true) or never (false) take focus, instead of `focus_new_windows`
+    pub focus: Option<bool>,
+    /// Border width for this window instead of `layout.border_width`
+    pub border_width: Option<u32>,
+    /// Float on every workspace, like `toggle_sticky`
+    pub sticky: Option<bool>,
+}
+
+impl WindowRule {
+    /// Checks if every matcher of this rule matches the window
+    pub fn matches(&self, info: &WindowInfo) -> bool {
+        let pattern_matches = |pattern: &Option<Pattern>, value: &str| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| pattern.matches(value))
+        };
+        pattern_matches(&self.class, &info.class)
+            && pattern_matches(&self.instance, &info.instance)
+            && pattern_matches(&self.title, &info.title)
+            && pattern_matches(&self.role, &info.role)
+            && self
+                .window_type
+                .as_ref()
+                .is_none_or(|window_type| *window_type == info.window_type)
+    }
+
+    /// Whether the rule says what to match on
+    fn has_matcher(&self) -> bool {
+        self.class.is_some()
+            || self.instance.is_some()
+            || self.title.is_some()
+            || self.role.is_some()
+            || self.window_type.is_some()
+    }
+
+    /// Whether the rule does anything
+    fn has_action(&self) -> bool {
+        self.workspace.is_some()
+            || self.floating.is_some()
+            || self.geometry.is_some()
+            || self.fullscreen.is_some()
+            || self.split_ratio.is_some()
+            || self.insert.is_some()
+            || self.focus.is_some()
+            || self.border_width.is_some()
+            || self.sticky.is_some()
+    }
+}
+
+impl Validate for WindowRule {
+    fn validate(&self) -> Result<()> {
+        if !self.has_matcher() {
+            return Err(anyhow::anyhow!(
+                "needs at least one of class, instance, title, role or window_type"
+            ));
+        }
+        if !self.has_action() {
+            return Err(anyhow::anyhow!("matches windows but has no action"));
+        }
+        if let Some(window_type) = &self.window_type
+            && !WINDOW_TYPES.contains(&window_type.as_str())
+        {
+            return Err(anyhow::anyhow!(
+                "unknown window_type {:?}, expected one of: {}",
+                window_type,
+                WINDOW_TYPES.join(", ")
+            ));
+        }
+        if let Some(RuleWorkspace::Name(name)) = &self.workspace
+            && name.trim().is_empty()
+        {
+            return Err(anyhow::anyhow!("workspace name must not be empty"));
+        }
+        let sticky = self.sticky == Some(true);
+        if self.geometry.is_some() && self.floating != Some(true) && !sticky {
+            return Err(anyhow::anyhow!("geometry needs floating = true"));
+        }
+        if sticky && self.workspace.is_some() {
+            return Err(anyhow::anyhow!(
+                "sticky windows are on every workspace, remove workspace"
+            ));
+        }
+        if let Some(ratio) = self.split_ratio {
+            validators::validate_ratio(ratio, "split_ratio")?;
+        }
+        if let Some(width) = self.border_width {
+            validators::validate_dimension(width, "border_width", 0, 50)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the matching rules decided for a window
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuleActions {
+    pub workspace: Option<WorkspaceTarget>,
+    pub floating: bool,
+    pub geometry: Option<Geometry>,
+    pub fullscreen: bool,
+    pub split_ratio: Option<f32>,
+    pub insert: Option<Direction>,
+    pub focus: Option<bool>,
+    pub border_width: Option<u32>,
+    pub sticky: bool,
+}
+
+/// Applies every rule matching the window in order; later rules override earlier ones
+pub fn evaluate(rules: &[WindowRule], info: &WindowInfo) -> RuleActions {
+    let mut actions = RuleActions::default();
+    for rule in rules.iter().filter(|rule| rule.matches(info)) {
+        if let Some(workspace) = &rule.workspace {
+            actions.workspace = Some(workspace.target());
+        }
+        if let Some(floating) = rule.floating {
+            actions.floating = floating;
+        }
+        if let Some(fullscreen) = rule.fullscreen {
+            actions.fullscreen = fullscreen;
+        }
+        if let Some(sticky) = rule.sticky {
+            actions.sticky = sticky;
+        }
+        actions.geometry = rule.geometry.or(actions.geometry);
+        actions.split_ratio = rule.split_ratio.or(actions.split_ratio);
+        actions.insert = rule.insert.or(actions.insert);
+        actions.focus = rule.focus.or(actions.focus);
+        actions.border_width = rule.border_width.or(actions.border_width);
+    }
+    actions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firefox() -> WindowInfo {
+        WindowInfo {
+            class: "Firefox".to_string(),
+            instance: "Navigator".to_string(),
+            title: "Picture-in-Picture".to_string(),
+            role: "PictureInPicture".to_string(),
+            window_type: "normal".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_pattern_glob_and_regex() {
+        let glob = Pattern::new("Fire*").unwrap();
+        assert!(glob.matches("Firefox"));
+        assert!(!glob.matches("firefox"));
+        // Globs match the whole value and treat regex characters literally
+        assert!(!Pattern::new("fox").unwrap().matches("Firefox"));
+        assert!(Pattern::new("a.b?").unwrap().matches("a.bc"));
+        assert!(!Pattern::new("a.b").unwrap().matches("axb"));
+
+        let regex = Pattern::new("/^Picture-in-(Picture|picture)$/").unwrap();
+        assert!(regex.matches("Picture-in-Picture"));
+        assert!(Pattern::new("/fox/").unwrap().matches("Firefox"));
+        assert!(Pattern::new("/(/").is_err());
+    }
+
+    #[test]
+    fn test_geometry() {
+        let geometry = Geometry::parse("800x600+10+20").unwrap();
+        assert_eq!(geometry.position, Some((10, 20)));
+        assert_eq!(geometry.to_string(), "800x600+10+20");
+
+        let centered = Geometry::parse("800x600").unwrap().place(7, 1920, 1080);
+        assert_eq!((centered.x, centered.y), (560, 240));
+        assert_eq!((centered.width, centered.height), (800, 600));
+
+        for invalid in ["800", "0x600", "800x600+10", "axb", ""] {
+            assert!(Geometry::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_rules_parse_and_evaluate() {
+        #[derive(Deserialize)]
+        struct Rules {
+            rules: Vec<WindowRule>,
+        }
+        let Rules { rules } = toml::from_str(
+            r#"
+            [[rules]]
+            class = "Firefox"
+            workspace = "web"
+
+            [[rules]]
+            title = "/Picture-in-Picture/"
+            floating = true
+            geometry = "640x360+1260+700"
+            focus = false
+            border_width = 0
+            sticky = true
+
+            [[rules]]
+            window_type = "dialog"
+            floating = true
+            "#,
+        )
+        .unwrap();
+        for rule in &rules {
+            rule.validate().unwrap();
+        }
+
+        let actions = evaluate(&rules, &firefox());
+        assert_eq!(
+            actions.workspace,
+            Some(WorkspaceTarget::Name("web".to_string()))
+        );
+        assert!(actions.floating);
+        assert_eq!(actions.geometry, Geometry::parse("640x360+1260+700").ok());
+        assert_eq!(actions.focus, Some(false));
+        assert_eq!(actions.border_width, Some(0));
+        assert!(actions.sticky);
+
+        let terminal = WindowInfo {
+            class: "Alacritty".to_string(),
+            window_type: "normal".to_string(),
+            ..WindowInfo::default()
+        };
+        assert_eq!(evaluate(&rules, &terminal), RuleActions::default());
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let parse = |text: &str| toml::from_str::<WindowRule>(text);
+        // Malformed values and unknown keys fail while parsing
+        assert!(parse(r#"class = "/[/""#).is_err());
+        assert!(parse(r#"geometry = "big""#).is_err());
+        assert!(parse(r#"insert = "sideways""#).is_err());
+        assert!(parse(r#"clas = "Firefox""#).is_err());
+
+        let invalid = [
+            r#"floating = true"#,
+            r#"class = "Firefox""#,
+            r#"window_type = "popup"
+               floating = true"#,
+            r#"class = "mpv"
+               geometry = "640x360""#,
+            r#"class = "mpv"
+               split_ratio = 1.5"#,
+            r#"class = "mpv"
+               workspace = " ""#,
+            r#"class = "mpv"
+               sticky = true
+               workspace = "web""#,
+        ];
+        for text in invalid {
+            let rule = parse(text).unwrap();
+            assert!(rule.validate().is_err(), "{text}");
+        }
+    }
+}
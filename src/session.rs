@@ -0,0 +1,161 @@
+//! Runtime state handed over to the new process on in-place restart
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use x11rb::protocol::xproto::Window;
+
+use crate::workspace::Workspace;
+
+/// Command-line flag that tells a freshly exec'd rustile where to find its saved state
+pub const RESTORE_STATE_FLAG: &str = "--restore-state";
+
+/// Everything needed to re-adopt windows after exec
+#[derive(Serialize, Deserialize)]
+pub struct SessionState {
+    pub current_workspace_index: usize,
+    pub workspaces: Vec<Workspace>,
+    pub intentionally_unmapped: HashSet<Window>,
+    #[serde(default)]
+    pub original_borders: HashMap<Window, u16>,
+    /// Windows in the scratchpad, hidden ones are also in `intentionally_unmapped`
+    #[serde(default)]
+    pub scratchpad: Vec<Window>,
+    /// Windows shown on every workspace
+    #[serde(default)]
+    pub sticky: HashSet<Window>,
+}
+
+impl SessionState {
+    /// Writes the state as JSON to a new file only we can read
+    /// The path may be in a shared directory like /tmp, so an existing file or symlink is
+    /// removed and never written through; if someone else's file is in the way, saving fails
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string(self)?;
+        match std::fs::remove_file(path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(json.as_bytes())?;
+        Ok(())
+    }
+
+    /// Reads the state and deletes the file, so a stale state is never restored twice
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        std::fs::remove_file(path)?;
+        let state: SessionState = serde_json::from_str(&content)?;
+        if state.workspaces.is_empty() {
+            return Err(anyhow::anyhow!("Saved state has no workspaces"));
+        }
+        Ok(state)
+    }
+}
+
+/// Returns the file the current process saves its state to before restarting
+pub fn state_path() -> PathBuf {
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    runtime_dir.join(format!("rustile-restart-{}.json", std::process::id()))
+}
+
+/// Extracts the saved state path from command-line arguments
+pub fn restore_path_from_args(args: impl IntoIterator<Item = String>) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == RESTORE_STATE_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_roundtrip() {
+        let mut workspace = Workspace::new();
+        workspace.add_window(10);
+        workspace.set_focused_window(Some(10));
+        workspace.add_window(20);
+        workspace.set_focused_window(Some(20));
+        workspace.set_zoomed_window(Some(20));
+
+        let state = SessionState {
+            current_workspace_index: 1,
+            workspaces: vec![Workspace::new(), workspace],
+            intentionally_unmapped: HashSet::from([30]),
+            original_borders: HashMap::from([(10, 2)]),
+            scratchpad: vec![30],
+            sticky: HashSet::from([20]),
+        };
+
+        let path = std::env::temp_dir().join(format!("rustile-test-{}.json", std::process::id()));
+        state.save(&path).unwrap();
+        let restored = SessionState::load(&path).unwrap();
+        assert!(!path.exists());
+
+        assert_eq!(restored.current_workspace_index, 1);
+        assert!(restored.intentionally_unmapped.contains(&30));
+        assert_eq!(restored.original_borders.get(&10), Some(&2));
+        assert_eq!(restored.scratchpad, vec![30]);
+        assert!(restored.sticky.contains(&20));
+        let workspace = &restored.workspaces[1];
+        assert_eq!(workspace.get_all_windows(), vec![10, 20]);
+        assert_eq!(workspace.focused_window(), Some(20));
+        assert_eq!(workspace.zoomed_window(), Some(20));
+        assert_eq!(workspace.mru_windows(), vec![20, 10]);
+    }
+
+    #[test]
+    fn test_save_does_not_follow_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir();
+        let target = dir.join(format!("rustile-victim-{}.txt", std::process::id()));
+        let path = dir.join(format!("rustile-link-{}.json", std::process::id()));
+        std::fs::write(&target, "keep").unwrap();
+        let _ = std::fs::remove_file(&path);
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+
+        let state = SessionState {
+            current_workspace_index: 0,
+            workspaces: vec![Workspace::new()],
+            intentionally_unmapped: HashSet::new(),
+            original_borders: HashMap::new(),
+            scratchpad: Vec::new(),
+            sticky: HashSet::new(),
+        };
+        state.save(&path).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "keep");
+        let metadata = std::fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.is_file());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert!(SessionState::load(&path).is_ok());
+        std::fs::remove_file(&target).unwrap();
+    }
+
+    #[test]
+    fn test_restore_path_from_args() {
+        let args = ["rustile", "--restore-state", "/tmp/state.json"].map(String::from);
+        assert_eq!(
+            restore_path_from_args(args),
+            Some(PathBuf::from("/tmp/state.json"))
+        );
+        assert_eq!(restore_path_from_args(["rustile".to_string()]), None);
+    }
+}
@@ -0,0 +1,71 @@
+//! Unix signal delivery into the event loop
+
+use anyhow::Result;
+use std::io::Read;
+use std::os::fd::{AsFd, BorrowedFd};
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub use signal_hook::consts::signal::{SIGCHLD, SIGHUP, SIGINT, SIGTERM};
+
+/// Records received signals and wakes the event loop through a pollable socket
+pub struct SignalWatcher {
+    /// Becomes readable whenever a watched signal arrives
+    wakeup: UnixStream,
+    /// Per-signal flags set by the signal handlers
+    flags: Vec<(i32, Arc<AtomicBool>)>,
+}
+
+impl SignalWatcher {
+    /// Installs handlers for the given signals
+    pub fn new(signals: &[i32]) -> Result<Self> {
+        let (wakeup, notifier) = UnixStream::pair()?;
+        wakeup.set_nonblocking(true)?;
+        notifier.set_nonblocking(true)?;
+
+        let mut flags = Vec::new();
+        for &signal in signals {
+            let flag = Arc::new(AtomicBool::new(false));
+            signal_hook::flag::register(signal, Arc::clone(&flag))?;
+            signal_hook::low_level::pipe::register(signal, notifier.try_clone()?)?;
+            flags.push((signal, flag));
+        }
+
+        Ok(Self { wakeup, flags })
+    }
+
+    /// Returns the signals received since the last call
+    pub fn pending(&mut self) -> Vec<i32> {
+        // Drain wakeup bytes so the socket stops polling readable
+        let mut buffer = [0u8; 64];
+        while matches!(self.wakeup.read(&mut buffer), Ok(n) if n > 0) {}
+
+        self.flags
+            .iter()
+            .filter(|(_, flag)| flag.swap(false, Ordering::Relaxed))
+            .map(|(signal, _)| *signal)
+            .collect()
+    }
+}
+
+impl AsFd for SignalWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.wakeup.as_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_reports_each_signal_once() {
+        let mut watcher = SignalWatcher::new(&[SIGHUP]).unwrap();
+        assert!(watcher.pending().is_empty());
+
+        signal_hook::low_level::raise(SIGHUP).unwrap();
+        assert_eq!(watcher.pending(), vec![SIGHUP]);
+        assert!(watcher.pending().is_empty());
+    }
+}
@@ -0,0 +1,19 @@
+//! Location of the IPC socket, shared by rustile and rustile-msg
+
+use std::path::PathBuf;
+
+/// Returns the IPC socket path: $RUSTILE_SOCKET, or $XDG_RUNTIME_DIR/rustile-$DISPLAY.sock
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("RUSTILE_SOCKET") {
+        return PathBuf::from(path);
+    }
+
+    let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    let display = std::env::var("DISPLAY")
+        .unwrap_or_default()
+        .replace('/', "_");
+
+    runtime_dir.join(format!("rustile-{display}.sock"))
+}
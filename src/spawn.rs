@@ -0,0 +1,191 @@
+//! Launching and reaping external programs
+
+use anyhow::Result;
+use rustix::process::WaitOptions;
+use std::fs::OpenOptions;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(debug_assertions)]
+use tracing::debug;
+use tracing::info;
+use x11rb::protocol::xproto::{Timestamp, Window};
+
+/// Window manager state exported to spawned programs
+#[derive(Debug, Clone, Default)]
+pub struct SpawnContext {
+    pub workspace: usize,
+    pub focused_window: Option<Window>,
+    /// Server time of the input that launched the program, 0 if unknown
+    pub user_time: Timestamp,
+    /// Extra variables, e.g. describing the event a hook runs for; set last so they win
+    pub env: Vec<(&'static str, String)>,
+}
+
+/// Returns the file children's stdout/stderr are appended to
+pub fn log_path() -> PathBuf {
+    dirs::state_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("rustile")
+        .join("children.log")
+}
+
+/// Runs `command` with `<shell> -c` in its own session, with stdio going to `log`
+/// Returns the child's pid; the child is reaped later by `reap_children`
+pub fn spawn(shell: &str, command: &str, context: SpawnContext, log: &Path) -> Result<u32> {
+    let mut cmd = Command::new(shell);
+    cmd.arg("-c")
+        .arg(command)
+        .env("RUSTILE_WORKSPACE", context.workspace.to_string())
+        .stdin(Stdio::null())
+        .stdout(open_log(log))
+        .stderr(open_log(log));
+
+    match context.focused_window {
+        Some(window) => cmd.env("RUSTILE_FOCUSED_WINDOW", window.to_string()),
+        None => cmd.env_remove("RUSTILE_FOCUSED_WINDOW"),
+    };
+
+    // Toolkits copy the _TIME suffix into _NET_WM_USER_TIME, so the new window
+    // counts as opened by the user and is allowed to take focus
+    match context.user_time {
+        0 => cmd.env_remove("DESKTOP_STARTUP_ID"),
+        time => cmd.env("DESKTOP_STARTUP_ID", startup_id(time)),
+    };
+
+    cmd.envs(context.env);
+
+    // SAFETY: setsid is async-signal-safe and touches no memory of the parent
+    unsafe {
+        cmd.pre_exec(|| rustix::process::setsid().map(|_| ()).map_err(Into::into));
+    }
+
+    let child = cmd
+        .spawn()
+        .map_err(|e| anyhow::anyhow!("Failed to launch {}: {}", command, e))?;
+    info!("Launched [{}]: {}", child.id(), command);
+    Ok(child.id())
+}
+
+/// Builds a startup notification ID that is unique per launch, carrying the launch time
+fn startup_id(time: Timestamp) -> String {
+    static LAUNCHES: AtomicU32 = AtomicU32::new(0);
+    let launch = LAUNCHES.fetch_add(1, Ordering::Relaxed);
+    format!("rustile{}-{}_TIME{}", std::process::id(), launch, time)
+}
+
+/// Opens the child log for appending, falling back to discarding output
+fn open_log(path: &Path) -> Stdio {
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map(Stdio::from)
+        .unwrap_or_else(|_| Stdio::null())
+}
+
+/// Checks if `pid` is `ancestor` or one of its descendants, following parents in /proc
+/// Programs that daemonize are reparented to init and no longer count
+pub fn is_descendant(pid: u32, ancestor: u32) -> bool {
+    let mut pid = pid;
+    // Bounded walk, in case /proc changes under us
+    for _ in 0..64 {
+        if pid == ancestor {
+            return true;
+        }
+        match parent_pid(pid) {
+            Some(parent) if parent > 1 => pid = parent,
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// Reads a process's parent pid from /proc/<pid>/stat
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // The command name in parentheses may contain spaces, so parse after its closing paren
+    let (_, rest) = stat.rsplit_once(')')?;
+    rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Collects every exited child without blocking, returning how many were reaped
+pub fn reap_children() -> usize {
+    let mut reaped = 0;
+    while let Ok(Some((_pid, _status))) = rustix::process::wait(WaitOptions::NOHANG) {
+        #[cfg(debug_assertions)]
+        debug!("Reaped child {:?} ({:?})", _pid, _status);
+        reaped += 1;
+    }
+    reaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_spawn_uses_shell_and_exports_context() {
+        let dir = std::env::temp_dir().join(format!("rustile-spawn-test-{}", std::process::id()));
+        let log = dir.join("children.log");
+        let context = SpawnContext {
+            workspace: 3,
+            focused_window: Some(42),
+            user_time: 1234,
+            env: vec![("RUSTILE_EVENT", "test".to_string())],
+        };
+
+        // Quoting, variables and pipes only work through a shell
+        let pid = spawn(
+            "/bin/sh",
+            "echo \"ws=$RUSTILE_WORKSPACE win=$RUSTILE_FOCUSED_WINDOW ${DESKTOP_STARTUP_ID##*_} $RUSTILE_EVENT\" | tr a-z A-Z",
+            context,
+            &log,
+        )
+        .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut output = String::new();
+        while Instant::now() < deadline {
+            output = std::fs::read_to_string(&log).unwrap_or_default();
+            if !output.is_empty() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        // Wait for our own child only: reap_children would also collect children of
+        // tests running in parallel, such as the one test_is_descendant waits for
+        let pid = rustix::process::Pid::from_raw(pid as i32).unwrap();
+        rustix::process::waitpid(Some(pid), WaitOptions::empty()).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(output.trim(), "WS=3 WIN=42 TIME1234 TEST");
+    }
+
+    #[test]
+    fn test_startup_id_is_unique_per_launch() {
+        let first = startup_id(1234);
+        let second = startup_id(1234);
+        assert_ne!(first, second);
+        assert!(first.ends_with("_TIME1234"));
+    }
+
+    #[test]
+    fn test_is_descendant() {
+        let me = std::process::id();
+        let mut child = Command::new("sleep").arg("5").spawn().unwrap();
+
+        assert!(is_descendant(me, me));
+        assert!(is_descendant(child.id(), me));
+        assert!(!is_descendant(me, child.id()));
+        assert!(!is_descendant(u32::MAX, me));
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+}
@@ -1,7 +1,12 @@
 //! Window manager core - coordinates X11 events and state
 
 use anyhow::Result;
+use rustix::event::{PollFd, PollFlags, Timespec, poll};
+use std::os::fd::OwnedFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
 use std::process::Command;
+use std::time::{Duration, Instant};
 #[cfg(debug_assertions)]
 use tracing::debug;
 use tracing::{error, info};
@@ -9,28 +14,126 @@ use x11rb::CURRENT_TIME;
 use x11rb::connection::Connection;
 use x11rb::protocol::Event;
 use x11rb::protocol::xproto::*;
+use x11rb::wrapper::ConnectionExt as _;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::bsp::Direction;
+use crate::command::{Action, Command as WmCommand, CommandChain, Condition, WorkspaceTarget};
+use crate::config::{Config, DEFAULT_MODE};
+use crate::events::{EventBus, WmEvent};
+use crate::ewmh;
+use crate::ipc::{
+    IpcServer, ModeReply, Query, TreeNode, WorkspaceInfo, WorkspaceTree, WorkspacesReply,
+    parse_subscribe,
+};
+use crate::keyboard::{KeyAction, ShortcutManager};
+use crate::rules::{self, Geometry, RuleActions};
+use crate::session::{RESTORE_STATE_FLAG, SessionState};
+use crate::signals::{SIGCHLD, SIGHUP, SIGINT, SIGTERM, SignalWatcher};
+use crate::spawn::{self, SpawnContext};
+use crate::workspace::Workspace;
+use crate::workspace_renderer::{WindowGeometry, WorkspaceRenderer};
 
-use std::collections::HashSet;
+/// How long windows of autostart and scratchpad programs are still placed as launched
+const LAUNCH_PLACEMENT_TIMEOUT: Duration = Duration::from_secs(60);
 
-use crate::keyboard::ShortcutManager;
-use crate::workspace::Workspace;
-use crate::workspace_renderer::WorkspaceRenderer;
+/// A program launched for a named workspace, whose windows should open there
+pub(crate) struct Launch {
+    pid: u32,
+    workspace: String,
+    expires: Instant,
+}
+
+/// A named scratchpad launched by `scratchpad_show`, whose window should open as the scratchpad
+pub(crate) struct ScratchpadLaunch {
+    class: String,
+    expires: Instant,
+}
+
+/// Scratchpad windows currently shown on `workspace`
+fn scratchpad_windows_on(workspace: &Workspace, scratchpad: &[Window]) -> Vec<Window> {
+    scratchpad
+        .iter()
+        .copied()
+        .filter(|&window| workspace.has_window(window))
+        .collect()
+}
+
+/// Windows walked by an alt-tab style cycle, in most recently used order
+pub(crate) struct WindowCycle {
+    windows: Vec<Window>,
+    /// Selected window; starts at the focused one
+    index: usize,
+}
+
+impl WindowCycle {
+    fn new(windows: Vec<Window>) -> Self {
+        Self { windows, index: 0 }
+    }
+
+    /// Moves the selection one window forward or back, wrapping around
+    fn step(&mut self, forward: bool) -> Window {
+        let len = self.windows.len();
+        self.index = if forward {
+            (self.index + 1) % len
+        } else {
+            (self.index + len - 1) % len
+        };
+        self.selected()
+    }
+
+    /// Gets the selected window
+    fn selected(&self) -> Window {
+        self.windows[self.index]
+    }
+}
 
 /// Main window manager coordinating X11 events and window state
 pub struct WindowManager<C: Connection> {
     pub(crate) conn: C,
+    /// Atoms interned once at start-up, used for every EWMH property
+    pub(crate) atoms: ewmh::Atoms,
     pub(crate) shortcut_manager: ShortcutManager,
     pub(crate) workspaces: Vec<Workspace>,
     pub(crate) current_workspace_index: usize,
     pub(crate) intentionally_unmapped: HashSet<Window>,
     pub(crate) screen_num: usize,
     pub(crate) workspace_renderer: WorkspaceRenderer,
+    pub(crate) ipc_server: Option<IpcServer>,
+    pub(crate) events: EventBus,
+    /// Last focus reported to subscribers, to detect focus changes
+    pub(crate) reported_focus: Option<Window>,
+    pub(crate) signals: SignalWatcher,
+    /// Recently used windows being walked by `cycle_next`/`cycle_prev`
+    pub(crate) cycle: Option<WindowCycle>,
+    /// Set by the `restart` command, handled once pending IPC replies are sent
+    pub(crate) restart_requested: bool,
+    /// Set by `shutdown` or SIGTERM/SIGINT to leave the event loop
+    pub(crate) shutdown_requested: bool,
+    /// Border widths windows had before we managed them, restored on teardown
+    pub(crate) original_borders: HashMap<Window, u16>,
+    /// Server time of the last key binding we ran; launched programs are stamped with it
+    pub(crate) last_user_time: Timestamp,
+    /// Server time focus last changed through user input; new windows with an
+    /// older `_NET_WM_USER_TIME` are marked urgent instead of taking focus
+    pub(crate) focus_time: Timestamp,
+    /// Recently launched per-workspace autostart programs
+    pub(crate) launches: Vec<Launch>,
+    /// Named workspaces whose autostart programs already ran
+    pub(crate) autostarted_workspaces: HashSet<String>,
+    /// Windows moved to the scratchpad, whether hidden or currently shown
+    pub(crate) scratchpad: Vec<Window>,
+    /// Classes of named scratchpads launched whose window has not appeared yet
+    pub(crate) scratchpad_launches: Vec<ScratchpadLaunch>,
+    /// Floating windows carried along to every workspace, always on the current one
+    pub(crate) sticky: HashSet<Window>,
 }
 
 impl<C: Connection> WindowManager<C> {
     /// Creates a new window manager instance
     pub fn new(conn: C, screen_num: usize) -> Result<Self> {
-        let config = crate::config::Config::load()?;
+        let config = Config::load()?;
         info!(
             "Loaded configuration with {} shortcuts",
             config.shortcuts().len()
@@ -55,7 +158,14 @@ impl<C: Connection> WindowManager<C> {
 
         info!("Successfully became the window manager");
 
+        shortcut_manager.init_key_tracking(&conn, root);
         shortcut_manager.register_shortcuts(&conn, root, config.shortcuts())?;
+        shortcut_manager.register_modes(&config.modes);
+        shortcut_manager.set_sequence_timeout(config.sequence_timeout());
+        shortcut_manager.log_summary();
+        let atoms = ewmh::Atoms::new(&conn)?.reply()?;
+        ewmh::set_supported(&conn, &atoms, root)?;
+        Self::publish_mode(&conn, &atoms, root, DEFAULT_MODE)?;
 
         // Initialize with a single empty workspace
         let workspaces = vec![Workspace::new()];
@@ -65,14 +175,39 @@ impl<C: Connection> WindowManager<C> {
 
         info!("Initialized with 1 empty workspace");
 
+        // IPC is optional: the WM stays usable from the keyboard if the socket fails
+        let ipc_server = match IpcServer::bind(&crate::ipc::socket_path()) {
+            Ok(server) => Some(server),
+            Err(e) => {
+                error!("Failed to start IPC server: {}", e);
+                None
+            }
+        };
+
         Ok(Self {
             conn,
+            atoms,
             shortcut_manager,
             workspaces,
             current_workspace_index,
             intentionally_unmapped,
             screen_num,
             workspace_renderer,
+            ipc_server,
+            events: EventBus::new(),
+            reported_focus: None,
+            cycle: None,
+            signals: SignalWatcher::new(&[SIGHUP, SIGTERM, SIGINT, SIGCHLD])?,
+            restart_requested: false,
+            shutdown_requested: false,
+            original_borders: HashMap::new(),
+            last_user_time: CURRENT_TIME,
+            focus_time: CURRENT_TIME,
+            launches: Vec::new(),
+            autostarted_workspaces: HashSet::new(),
+            scratchpad: Vec::new(),
+            scratchpad_launches: Vec::new(),
+            sticky: HashSet::new(),
         })
     }
 
@@ -87,18 +222,480 @@ impl<C: Connection> WindowManager<C> {
     }
 
     /// Creates a new workspace and switches to it
-    pub fn create_workspace(&mut self) {
-        info!("Creating new workspace");
-        let old_workspace_index = self.current_workspace_index;
-        self.workspaces.push(Workspace::new());
-        self.current_workspace_index = self.workspaces.len() - 1;
-        info!(
-            "Created workspace {}, total workspaces: {}",
-            self.current_workspace_index,
-            self.workspaces.len()
-        );
-        // Switch to the newly created workspace
-        self.perform_workspace_switch(old_workspace_index);
+    /// A named workspace that already exists is switched to instead
+    pub fn create_workspace(&mut self, name: Option<String>) {
+        let index = match name {
+            Some(name) => self.find_or_create_workspace(&name),
+            None => {
+                self.workspaces.push(Workspace::new());
+                let index = self.workspaces.len() - 1;
+                self.events.emit(WmEvent::WorkspaceCreated { index });
+                index
+            }
+        };
+        self.switch_to_workspace(index);
+    }
+
+    /// Returns the index of the workspace with this name, creating it if needed
+    fn find_or_create_workspace(&mut self, name: &str) -> usize {
+        if let Some(index) = self.workspaces.iter().position(|w| w.name() == Some(name)) {
+            return index;
+        }
+        self.workspaces.push(Workspace::with_name(name.to_string()));
+        let index = self.workspaces.len() - 1;
+        self.events.emit(WmEvent::WorkspaceCreated { index });
+        self.autostart_workspace(index);
+        index
+    }
+
+    /// Launches the `[autostart]` programs: `exec` only on a fresh start, `exec_always` every time
+    pub fn autostart(&mut self, fresh_start: bool) {
+        let autostart = self.workspace_renderer.config().autostart().clone();
+        let once = autostart.exec.iter().filter(|_| fresh_start);
+        for command in once.chain(&autostart.exec_always) {
+            info!("Autostart: {}", command);
+            // Failures are logged by spawn and must not stop the others
+            let _ = self.spawn(command);
+        }
+    }
+
+    /// Launches a named workspace's autostart programs the first time it is created,
+    /// remembering their pids so their windows open on it
+    fn autostart_workspace(&mut self, index: usize) {
+        let Some(name) = self.workspaces[index].name().map(str::to_string) else {
+            return;
+        };
+        let Some(commands) = self
+            .workspace_renderer
+            .config()
+            .autostart()
+            .workspaces
+            .get(&name)
+            .cloned()
+        else {
+            return;
+        };
+        if !self.autostarted_workspaces.insert(name.clone()) {
+            return;
+        }
+
+        for command in commands {
+            info!("Autostart on workspace {}: {}", name, command);
+            if let Ok(pid) = self.spawn_on(&command, index) {
+                self.launches.push(Launch {
+                    pid,
+                    workspace: name.clone(),
+                    expires: Instant::now() + LAUNCH_PLACEMENT_TIMEOUT,
+                });
+            }
+        }
+    }
+
+    /// Finds the workspace a window's program was launched for, by its `_NET_WM_PID`
+    fn launch_workspace(&mut self, window: Window) -> Result<Option<WorkspaceTarget>> {
+        let now = Instant::now();
+        self.launches.retain(|launch| launch.expires > now);
+        if self.launches.is_empty() {
+            return Ok(None);
+        }
+        let Some(pid) = ewmh::window_pid(&self.conn, &self.atoms, window) else {
+            return Ok(None);
+        };
+        Ok(self
+            .launches
+            .iter()
+            .find(|launch| spawn::is_descendant(pid, launch.pid))
+            .map(|launch| WorkspaceTarget::Name(launch.workspace.clone())))
+    }
+
+    /// Resolves a workspace target to an index; named workspaces are created on demand
+    fn resolve_workspace(&mut self, target: &WorkspaceTarget) -> Result<usize> {
+        match target {
+            WorkspaceTarget::Index(index) if *index < self.workspaces.len() => Ok(*index),
+            WorkspaceTarget::Index(index) => Err(anyhow::anyhow!(
+                "No workspace {} (have {})",
+                index,
+                self.workspaces.len()
+            )),
+            WorkspaceTarget::Name(name) => Ok(self.find_or_create_workspace(name)),
+        }
+    }
+
+    /// Switches to the workspace at `index`
+    fn switch_to_workspace(&mut self, index: usize) {
+        if index == self.current_workspace_index {
+            return;
+        }
+
+        let old_index = self.current_workspace_index;
+        self.current_workspace_index = index;
+        self.events.emit(WmEvent::WorkspaceSwitched {
+            from: old_index,
+            to: index,
+        });
+        self.perform_workspace_switch(old_index);
+    }
+
+    /// Moves the focused window to another workspace, keeping the current one in view
+    pub fn move_window_to_workspace(&mut self, target: &WorkspaceTarget) -> Result<()> {
+        let Some(window) = self.current_workspace().focused_window() else {
+            info!("No focused window to move");
+            return Ok(());
+        };
+        let target_index = self.resolve_workspace(target)?;
+        if target_index == self.current_workspace_index {
+            return Ok(());
+        }
+        // Sending a window somewhere means it no longer belongs everywhere
+        self.unstick(window)?;
+
+        let policy = self.workspace_renderer.config().focus_on_close();
+        let workspace = self.current_workspace_mut();
+        if workspace.fullscreen_window() == Some(window) {
+            workspace.clear_fullscreen();
+        }
+        // Floating geometry, rule borders and urgency travel with the window
+        let floating = workspace.floating_geometry(window);
+        let border_width = workspace.border_width(window);
+        let urgent = workspace.is_urgent(window);
+        workspace.remove_window_with_focus(window, policy);
+
+        let target_workspace = &mut self.workspaces[target_index];
+        match floating {
+            Some(geometry) => target_workspace.add_floating(geometry),
+            None => target_workspace.add_window(window),
+        }
+        if let Some(width) = border_width {
+            target_workspace.set_border_width(window, width);
+        }
+        target_workspace.set_focused_window(Some(window));
+        self.carry_urgency(window, target_index, urgent);
+
+        self.intentionally_unmapped.insert(window);
+        self.conn.unmap_window(window)?;
+        info!("Moved window {:?} to workspace {}", window, target_index);
+
+        self.apply_current_workspace()
+    }
+
+    /// Hides the focused window in the scratchpad
+    fn move_to_scratchpad(&mut self) -> Result<()> {
+        let Some(window) = self.current_workspace().focused_window() else {
+            info!("No focused window to move to the scratchpad");
+            return Ok(());
+        };
+        self.hide_scratchpad(window)
+    }
+
+    /// Takes a window off its workspace and keeps it unmapped until `scratchpad_show`
+    fn hide_scratchpad(&mut self, window: Window) -> Result<()> {
+        self.unstick(window)?;
+        let workspace = self.take_window(window);
+        if !self.scratchpad.contains(&window) {
+            self.scratchpad.push(window);
+        }
+        self.intentionally_unmapped.insert(window);
+        self.conn.unmap_window(window)?;
+        info!("Moved window {:?} to the scratchpad", window);
+
+        if workspace == Some(self.current_workspace_index) {
+            self.apply_current_workspace()?;
+        }
+        Ok(())
+    }
+
+    /// Shows a scratchpad window (only those of `class` if given) on the current workspace,
+    /// or hides it again if it is focused; a named scratchpad without a window is launched
+    fn scratchpad_show(&mut self, class: Option<&str>) -> Result<()> {
+        let candidates: Vec<Window> = match class {
+            Some(class) => self
+                .scratchpad
+                .iter()
+                .copied()
+                .filter(|&window| {
+                    ewmh::window_info(&self.conn, &self.atoms, window)
+                        .is_ok_and(|info| info.has_class(class))
+                })
+                .collect(),
+            None => self.scratchpad.clone(),
+        };
+
+        let current = self.current_workspace();
+        if let Some(focused) = current.focused_window()
+            && candidates.contains(&focused)
+        {
+            return self.hide_scratchpad(focused);
+        }
+        if let Some(&window) = candidates.iter().find(|&&w| current.has_window(w)) {
+            self.current_workspace_mut()
+                .set_focused_window(Some(window));
+            return self.apply_current_workspace();
+        }
+        // Hidden, or shown on another workspace: bring it here
+        if let Some(&window) = candidates.first() {
+            return self.show_scratchpad(window);
+        }
+
+        let Some(class) = class else {
+            info!("The scratchpad is empty");
+            return Ok(());
+        };
+        let Some(scratchpad) = self.workspace_renderer.config().scratchpads().get(class) else {
+            info!("No scratchpad window of class {:?}", class);
+            return Ok(());
+        };
+        let now = Instant::now();
+        self.scratchpad_launches
+            .retain(|launch| launch.expires > now);
+        if self.scratchpad_launches.iter().any(|l| l.class == *class) {
+            info!("Scratchpad {:?} is still starting", class);
+            return Ok(());
+        }
+        let command = scratchpad.command.clone();
+        self.spawn(&command)?;
+        self.scratchpad_launches.push(ScratchpadLaunch {
+            class: class.to_string(),
+            expires: now + LAUNCH_PLACEMENT_TIMEOUT,
+        });
+        Ok(())
+    }
+
+    /// Shows a scratchpad window as a centered floating window on the current workspace
+    fn show_scratchpad(&mut self, window: Window) -> Result<()> {
+        self.take_window(window);
+        if self.current_workspace().fullscreen_window().is_some() {
+            self.leave_fullscreen();
+        }
+
+        let info = ewmh::window_info(&self.conn, &self.atoms, window).unwrap_or_default();
+        let geometry = self.scratchpad_geometry(&info);
+        let screen = &self.conn.setup().roots[self.screen_num];
+        let geometry = geometry.place(window, screen.width_in_pixels, screen.height_in_pixels);
+        let workspace = self.current_workspace_mut();
+        workspace.add_floating(geometry);
+        workspace.set_focused_window(Some(window));
+
+        self.intentionally_unmapped.remove(&window);
+        self.conn.map_window(window)?;
+        info!("Showing scratchpad window {:?}", window);
+        self.apply_current_workspace()
+    }
+
+    /// Size of a shown scratchpad window: its `[scratchpads]` geometry or 60% of the screen
+    fn scratchpad_geometry(&self, info: &rules::WindowInfo) -> Geometry {
+        let configured = self
+            .workspace_renderer
+            .config()
+            .scratchpads()
+            .iter()
+            .find(|(class, _)| info.has_class(class))
+            .and_then(|(_, scratchpad)| scratchpad.geometry);
+        configured.unwrap_or_else(|| {
+            let screen = &self.conn.setup().roots[self.screen_num];
+            Geometry {
+                width: u32::from(screen.width_in_pixels) * 3 / 5,
+                height: u32::from(screen.height_in_pixels) * 3 / 5,
+                position: None,
+            }
+        })
+    }
+
+    /// Removes a window from whichever workspace holds it, leaving fullscreen if it was
+    /// the fullscreen window; returns the workspace index
+    fn take_window(&mut self, window: Window) -> Option<usize> {
+        let index = self.workspaces.iter().position(|w| w.has_window(window))?;
+        if self.workspaces[index].fullscreen_window() == Some(window) {
+            if index == self.current_workspace_index {
+                self.leave_fullscreen();
+            } else {
+                self.workspaces[index].clear_fullscreen();
+            }
+        }
+        let policy = self.workspace_renderer.config().focus_on_close();
+        let urgent = self.workspaces[index].is_urgent(window);
+        self.workspaces[index].remove_window_with_focus(window, policy);
+        // The window leaves every workspace, so nothing tracks its urgency any more
+        if urgent {
+            if let Err(e) = ewmh::set_wm_state(
+                &self.conn,
+                &self.atoms,
+                window,
+                self.atoms._NET_WM_STATE_DEMANDS_ATTENTION,
+                false,
+            ) {
+                error!("Failed to clear urgency of window {:?}: {}", window, e);
+            }
+            self.events.emit(WmEvent::UrgencyChanged {
+                window,
+                workspace: index,
+                urgent: false,
+            });
+        }
+        Some(index)
+    }
+
+    /// Keeps a window that was urgent before moving urgent on workspace `to`
+    /// The event tells subscribers which workspace now holds it
+    fn carry_urgency(&mut self, window: Window, to: usize, urgent: bool) {
+        if urgent && self.workspaces[to].set_urgent(window, true) {
+            self.events.emit(WmEvent::UrgencyChanged {
+                window,
+                workspace: to,
+                urgent: true,
+            });
+        }
+    }
+
+    /// Exits fullscreen on the current workspace and maps the windows it hid
+    /// The caller renders the workspace afterwards
+    fn leave_fullscreen(&mut self) {
+        let Some(fullscreen) = self.current_workspace().fullscreen_window() else {
+            return;
+        };
+        self.current_workspace_mut().set_fullscreen_window(None);
+        self.events.emit(WmEvent::FullscreenToggled {
+            window: fullscreen,
+            enabled: false,
+        });
+
+        for window in self.current_workspace().get_all_windows() {
+            self.intentionally_unmapped.remove(&window);
+            if let Err(e) = self.conn.map_window(window) {
+                error!("Failed to map window {:?}: {}", window, e);
+            }
+        }
+    }
+
+    /// Toggles whether the focused window stays on every workspace
+    fn toggle_sticky(&mut self) -> Result<()> {
+        let Some(window) = self.current_workspace().focused_window() else {
+            info!("No focused window to make sticky");
+            return Ok(());
+        };
+        self.set_sticky(window, !self.sticky.contains(&window))
+    }
+
+    /// Makes a window sticky, floating it on the current workspace, or ends that;
+    /// an unstuck window stays floating where it is
+    fn set_sticky(&mut self, window: Window, sticky: bool) -> Result<()> {
+        if self.sticky.contains(&window) == sticky {
+            return Ok(());
+        }
+        if !sticky {
+            self.unstick(window)?;
+            return self.apply_current_workspace();
+        }
+        let Some(index) = self.workspaces.iter().position(|w| w.has_window(window)) else {
+            info!("Window {:?} is not managed, cannot make it sticky", window);
+            return Ok(());
+        };
+
+        // Float it where it is on screen now
+        let workspace = &self.workspaces[index];
+        let was_focused = workspace.focused_window() == Some(window);
+        let border_width = workspace.border_width(window);
+        let geometry = match workspace.floating_geometry(window) {
+            Some(geometry) => geometry,
+            None => {
+                let geometry = self.conn.get_geometry(window)?.reply()?;
+                WindowGeometry {
+                    window,
+                    x: i32::from(geometry.x),
+                    y: i32::from(geometry.y),
+                    width: u32::from(geometry.width),
+                    height: u32::from(geometry.height),
+                }
+            }
+        };
+        self.take_window(window);
+        let workspace = self.current_workspace_mut();
+        workspace.add_floating(geometry);
+        if let Some(width) = border_width {
+            workspace.set_border_width(window, width);
+        }
+        if was_focused {
+            workspace.set_focused_window(Some(window));
+        }
+        if index != self.current_workspace_index {
+            self.intentionally_unmapped.remove(&window);
+            self.conn.map_window(window)?;
+        }
+
+        self.sticky.insert(window);
+        self.publish_sticky(window, true)?;
+        self.events.emit(WmEvent::StickyToggled {
+            window,
+            enabled: true,
+        });
+        self.apply_current_workspace()
+    }
+
+    /// Ends a window's sticky state without re-rendering; a no-op if it is not sticky
+    fn unstick(&mut self, window: Window) -> Result<()> {
+        if !self.sticky.remove(&window) {
+            return Ok(());
+        }
+        self.events.emit(WmEvent::StickyToggled {
+            window,
+            enabled: false,
+        });
+        self.publish_sticky(window, false)
+    }
+
+    /// Mirrors the sticky state in `_NET_WM_STATE` and `_NET_WM_DESKTOP` for pagers and bars
+    fn publish_sticky(&self, window: Window, sticky: bool) -> Result<()> {
+        let state = self.atoms._NET_WM_STATE_STICKY;
+        ewmh::set_wm_state(&self.conn, &self.atoms, window, state, sticky)?;
+        let desktop = sticky.then_some(ewmh::ALL_DESKTOPS);
+        ewmh::set_desktop(&self.conn, &self.atoms, window, desktop)
+    }
+
+    /// Moves the sticky windows of one workspace to another, keeping their geometry
+    /// Returns true if any window was moved
+    fn carry_sticky_windows(&mut self, from: usize, to: usize) -> bool {
+        let policy = self.workspace_renderer.config().focus_on_close();
+        let mut carried = false;
+        for window in self.workspaces[from].get_all_windows() {
+            if !self.sticky.contains(&window) {
+                continue;
+            }
+            let workspace = &mut self.workspaces[from];
+            let Some(geometry) = workspace.floating_geometry(window) else {
+                continue;
+            };
+            if workspace.fullscreen_window() == Some(window) {
+                workspace.clear_fullscreen();
+                self.events.emit(WmEvent::FullscreenToggled {
+                    window,
+                    enabled: false,
+                });
+            }
+            let workspace = &mut self.workspaces[from];
+            let border_width = workspace.border_width(window);
+            let urgent = workspace.is_urgent(window);
+            workspace.remove_window_with_focus(window, policy);
+
+            let workspace = &mut self.workspaces[to];
+            workspace.add_floating(geometry);
+            if let Some(width) = border_width {
+                workspace.set_border_width(window, width);
+            }
+            self.carry_urgency(window, to, urgent);
+            carried = true;
+        }
+        carried
+    }
+
+    /// Re-renders the current workspace, honouring fullscreen state
+    fn apply_current_workspace(&mut self) -> Result<()> {
+        match self.current_workspace().fullscreen_window() {
+            Some(fullscreen) => self.apply_fullscreen_layout(fullscreen),
+            None => {
+                let workspace = &self.workspaces[self.current_workspace_index];
+                self.workspace_renderer
+                    .apply_workspace(&mut self.conn, workspace)
+            }
+        }
     }
 
     /// Deletes the current workspace
@@ -115,6 +712,21 @@ impl<C: Connection> WindowManager<C> {
             self.workspaces.len()
         );
 
+        // Sticky windows move on to the workspace shown next instead of closing
+        let next_index = if self.current_workspace_index + 1 < self.workspaces.len() {
+            self.current_workspace_index + 1
+        } else {
+            self.current_workspace_index - 1
+        };
+        let carried = self.carry_sticky_windows(self.current_workspace_index, next_index);
+
+        // Scratchpad windows being shown go back to the scratchpad instead of closing
+        for window in scratchpad_windows_on(self.current_workspace(), &self.scratchpad) {
+            if let Err(e) = self.hide_scratchpad(window) {
+                error!("Failed to hide scratchpad window {:?}: {}", window, e);
+            }
+        }
+
         // Get all windows in the current workspace
         let windows_to_close = self.current_workspace().get_all_windows();
 
@@ -131,7 +743,8 @@ impl<C: Connection> WindowManager<C> {
         }
 
         // Remove the workspace
-        self.workspaces.remove(self.current_workspace_index);
+        let deleted_index = self.current_workspace_index;
+        self.workspaces.remove(deleted_index);
 
         // Adjust current_workspace_index if it's out of bounds
         if self.current_workspace_index >= self.workspaces.len() {
@@ -148,6 +761,8 @@ impl<C: Connection> WindowManager<C> {
         }
 
         // Restore focus to the new workspace's focused window
+        let policy = self.workspace_renderer.config().focus_on_close();
+        self.current_workspace_mut().restore_focus(policy);
         if let Some(focused) = self.current_workspace().focused_window()
             && let Err(e) =
                 self.conn
@@ -155,18 +770,26 @@ impl<C: Connection> WindowManager<C> {
         {
             error!("Failed to set focus to window {:?}: {}", focused, e);
         }
+        if carried && let Err(e) = self.apply_current_workspace() {
+            error!("Failed to render workspace after deletion: {}", e);
+        }
 
-        // Flush X11 commands
-        if let Err(e) = self.conn.flush() {
+        // Flush X11 commands (deferred while running a command chain)
+        if !self.workspace_renderer.is_batching()
+            && let Err(e) = self.conn.flush()
+        {
             error!("Failed to flush X11 connection: {}", e);
         }
 
         info!(
-            "Deleted workspace, closed {} windows, now at workspace {}, total workspaces: {}",
+            "Closed {} windows, now at workspace {}, total workspaces: {}",
             windows_to_close.len(),
             self.current_workspace_index,
             self.workspaces.len()
         );
+        self.events.emit(WmEvent::WorkspaceDeleted {
+            index: deleted_index,
+        });
     }
 
     /// Switches to the next workspace (circular)
@@ -175,16 +798,7 @@ impl<C: Connection> WindowManager<C> {
             return; // Nothing to switch
         }
 
-        let old_index = self.current_workspace_index;
-        self.current_workspace_index = (self.current_workspace_index + 1) % self.workspaces.len();
-
-        info!(
-            "Switched workspace: {} -> {} (next)",
-            old_index, self.current_workspace_index
-        );
-
-        // Perform window visibility switch
-        self.perform_workspace_switch(old_index);
+        self.switch_to_workspace((self.current_workspace_index + 1) % self.workspaces.len());
     }
 
     /// Switches to the previous workspace (circular)
@@ -193,24 +807,19 @@ impl<C: Connection> WindowManager<C> {
             return; // Nothing to switch
         }
 
-        let old_index = self.current_workspace_index;
-        self.current_workspace_index = if self.current_workspace_index == 0 {
+        let index = if self.current_workspace_index == 0 {
             self.workspaces.len() - 1
         } else {
             self.current_workspace_index - 1
         };
-
-        info!(
-            "Switched workspace: {} -> {} (prev)",
-            old_index, self.current_workspace_index
-        );
-
-        // Perform window visibility switch
-        self.perform_workspace_switch(old_index);
+        self.switch_to_workspace(index);
     }
 
     /// Performs the actual workspace switch: unmaps old windows, maps new windows
     fn perform_workspace_switch(&mut self, old_workspace_index: usize) {
+        // Sticky windows come along instead of being unmapped
+        let carried = self.carry_sticky_windows(old_workspace_index, self.current_workspace_index);
+
         // Get windows from old workspace
         let old_windows = self.workspaces[old_workspace_index].get_all_windows();
 
@@ -234,6 +843,8 @@ impl<C: Connection> WindowManager<C> {
         }
 
         // Restore focus to the new workspace's focused window
+        let policy = self.workspace_renderer.config().focus_on_close();
+        self.current_workspace_mut().restore_focus(policy);
         if let Some(focused) = self.current_workspace().focused_window()
             && let Err(e) =
                 self.conn
@@ -241,9 +852,15 @@ impl<C: Connection> WindowManager<C> {
         {
             error!("Failed to set focus to window {:?}: {}", focused, e);
         }
+        // Newly mapped windows stack on top, so sticky ones are raised again
+        if carried && let Err(e) = self.apply_current_workspace() {
+            error!("Failed to render workspace after switch: {}", e);
+        }
 
-        // Flush X11 commands
-        if let Err(e) = self.conn.flush() {
+        // Flush X11 commands (deferred while running a command chain)
+        if !self.workspace_renderer.is_batching()
+            && let Err(e) = self.conn.flush()
+        {
             error!("Failed to flush X11 connection: {}", e);
         }
 
@@ -254,21 +871,482 @@ impl<C: Connection> WindowManager<C> {
         );
     }
 
-    /// Runs the main event loop
-    pub fn run(mut self) -> Result<()> {
+    /// Runs the main event loop, multiplexing the X11 connection and the IPC socket
+    /// Returns after a shutdown request; call `teardown` afterwards
+    pub fn run(&mut self, x11_fd: OwnedFd) -> Result<()> {
         info!("Starting window manager event loop");
 
         loop {
+            // Drain events already buffered by x11rb before blocking in poll
             self.conn.flush()?;
-            let event = self.conn.wait_for_event()?;
+            while let Some(event) = self.conn.poll_for_event()? {
+                if let Err(e) = self.handle_event(event) {
+                    error!("Error handling event: {:?}", e);
+                    // Continue running despite errors
+                }
+            }
+            self.conn.flush()?;
+
+            self.dispatch_events();
+
+            let ipc_ready = self.wait_for_input(&x11_fd)?;
+            if let Err(e) = self.shortcut_manager.expire_sequence(&self.conn) {
+                error!("Failed to cancel key sequence: {}", e);
+            }
+            self.handle_signals();
+            if ipc_ready {
+                self.handle_ipc_requests();
+            }
+            self.dispatch_events();
+
+            if self.shutdown_requested {
+                info!("Leaving event loop");
+                return Ok(());
+            }
+
+            if self.restart_requested {
+                self.restart_requested = false;
+                if let Err(e) = self.restart() {
+                    error!("Restart failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Hands the display back in a usable state: every window mapped, borders restored,
+    /// key grabs released and focus returned to PointerRoot. Best effort, errors are logged.
+    pub fn teardown(&mut self) {
+        self.events.emit(WmEvent::Shutdown);
+        self.dispatch_events();
+
+        let mut windows: HashSet<Window> = self.intentionally_unmapped.drain().collect();
+        for workspace in &self.workspaces {
+            windows.extend(workspace.get_all_windows());
+        }
+
+        for &window in &windows {
+            if let Err(e) = self.conn.map_window(window) {
+                error!("Failed to map window {:?} on teardown: {}", window, e);
+            }
+            if let Some(&border_width) = self.original_borders.get(&window) {
+                let aux = ConfigureWindowAux::new().border_width(u32::from(border_width));
+                if let Err(e) = self.conn.configure_window(window, &aux) {
+                    error!("Failed to restore border of window {:?}: {}", window, e);
+                }
+            }
+        }
+
+        let root = self.conn.setup().roots[self.screen_num].root;
+        if let Err(e) = self.shortcut_manager.unregister_shortcuts(&self.conn, root) {
+            error!("Failed to release key grabs: {}", e);
+        }
+
+        let pointer_root = u32::from(InputFocus::POINTER_ROOT);
+        if let Err(e) =
+            self.conn
+                .set_input_focus(InputFocus::POINTER_ROOT, pointer_root, CURRENT_TIME)
+        {
+            error!("Failed to reset input focus: {}", e);
+        }
+
+        if let Err(e) = self.conn.flush() {
+            error!("Failed to flush X11 connection on teardown: {}", e);
+        }
+        info!("Teardown complete, {} windows handed back", windows.len());
+    }
+
+    /// Saves runtime state and replaces this process with a fresh rustile that restores it
+    fn restart(&mut self) -> Result<()> {
+        let state = SessionState {
+            current_workspace_index: self.current_workspace_index,
+            workspaces: std::mem::take(&mut self.workspaces),
+            intentionally_unmapped: self.intentionally_unmapped.clone(),
+            original_borders: self.original_borders.clone(),
+            scratchpad: self.scratchpad.clone(),
+            sticky: self.sticky.clone(),
+        };
+        let path = crate::session::state_path();
+        let saved = state.save(&path);
+        self.workspaces = state.workspaces;
+        saved?;
+
+        // Prefer argv[0] so an upgraded binary on PATH is picked up
+        let program = std::env::args()
+            .next()
+            .map(Ok)
+            .unwrap_or_else(|| std::env::current_exe().map(|p| p.display().to_string()))?;
+        info!("Restarting rustile in place: {} {:?}", program, path);
+
+        // Release the socket so the new process can bind it
+        self.ipc_server = None;
+        self.conn.flush()?;
+
+        let e = Command::new(&program)
+            .arg(RESTORE_STATE_FLAG)
+            .arg(&path)
+            .exec();
+
+        // exec only returns on failure; keep running with the current state
+        let _ = std::fs::remove_file(&path);
+        self.ipc_server = IpcServer::bind(&crate::ipc::socket_path()).ok();
+        Err(anyhow::anyhow!("Failed to exec {}: {}", program, e))
+    }
+
+    /// Re-adopts the windows recorded by a previous process before it restarted
+    pub fn restore_session(&mut self, path: &Path) -> Result<()> {
+        let mut state = SessionState::load(path)?;
+
+        // Windows may have been closed while no window manager was running
+        let policy = self.workspace_renderer.config().focus_on_close();
+        for workspace in &mut state.workspaces {
+            for window in workspace.get_all_windows() {
+                if self.conn.get_window_attributes(window)?.reply().is_err() {
+                    info!("Window {:?} no longer exists, dropping it", window);
+                    if workspace.fullscreen_window() == Some(window) {
+                        workspace.clear_fullscreen();
+                    }
+                    workspace.remove_window_with_focus(window, policy);
+                }
+            }
+        }
+
+        self.current_workspace_index = state
+            .current_workspace_index
+            .min(state.workspaces.len() - 1);
+        self.workspaces = state.workspaces;
+        // Workspaces that survived the restart do not autostart again when revisited
+        self.autostarted_workspaces = self
+            .workspaces
+            .iter()
+            .filter_map(|workspace| workspace.name().map(str::to_string))
+            .collect();
+        self.original_borders = state.original_borders;
+        self.scratchpad = state.scratchpad;
+        for window in self.scratchpad.clone() {
+            if self.conn.get_window_attributes(window)?.reply().is_err() {
+                self.scratchpad.retain(|&w| w != window);
+            }
+        }
+        let current = &self.workspaces[self.current_workspace_index];
+        self.sticky = state.sticky;
+        self.sticky.retain(|&window| current.has_window(window));
+        self.intentionally_unmapped = state.intentionally_unmapped;
+        let workspaces = &self.workspaces;
+        let scratchpad = &self.scratchpad;
+        self.intentionally_unmapped.retain(|&window| {
+            workspaces.iter().any(|w| w.has_window(window)) || scratchpad.contains(&window)
+        });
+
+        // Hidden workspaces stay unmapped, the current one is shown again
+        for (index, workspace) in self.workspaces.iter().enumerate() {
+            if index == self.current_workspace_index {
+                continue;
+            }
+            for window in workspace.get_all_windows() {
+                self.intentionally_unmapped.insert(window);
+                self.conn.unmap_window(window)?;
+            }
+        }
+        match self.current_workspace().fullscreen_window() {
+            Some(fullscreen) => self.apply_fullscreen_layout(fullscreen)?,
+            None => {
+                for window in self.current_workspace().get_all_windows() {
+                    self.intentionally_unmapped.remove(&window);
+                }
+                let workspace = &self.workspaces[self.current_workspace_index];
+                self.workspace_renderer
+                    .apply_workspace(&mut self.conn, workspace)?;
+            }
+        }
+
+        info!(
+            "Restored {} workspaces, now at workspace {}",
+            self.workspaces.len(),
+            self.current_workspace_index
+        );
+        Ok(())
+    }
 
-            if let Err(e) = self.handle_event(event) {
-                error!("Error handling event: {:?}", e);
-                // Continue running despite errors
+    /// Reacts to Unix signals received since the last loop iteration
+    fn handle_signals(&mut self) {
+        for signal in self.signals.pending() {
+            match signal {
+                SIGHUP => {
+                    info!("Received SIGHUP, reloading configuration");
+                    if let Err(e) = self.reload_config() {
+                        error!("{}", e);
+                    }
+                }
+                SIGTERM | SIGINT => {
+                    info!("Received signal {}, shutting down", signal);
+                    self.shutdown_requested = true;
+                }
+                SIGCHLD => {
+                    spawn::reap_children();
+                }
+                _ => {}
             }
         }
     }
 
+    /// Reloads the config file, keeping the current config if it is invalid
+    pub(crate) fn reload_config(&mut self) -> Result<()> {
+        let config = Config::load()
+            .map_err(|e| anyhow::anyhow!("Config reload failed, keeping old config: {}", e))?;
+
+        self.rebind_keys(&config)
+            .map_err(|e| anyhow::anyhow!("Config reload failed, keeping old config: {}", e))?;
+        self.workspace_renderer.set_config(config);
+        // Scratchpads may have been renamed or removed while their programs start
+        self.scratchpad_launches.clear();
+
+        for (index, workspace) in self.workspaces.iter().enumerate() {
+            if index == self.current_workspace_index {
+                continue;
+            }
+            self.workspace_renderer
+                .apply_hidden_workspace(&mut self.conn, workspace)?;
+        }
+        self.apply_current_workspace()?;
+
+        self.events.emit(WmEvent::ConfigReloaded);
+        self.autostart(false);
+        Ok(())
+    }
+
+    /// Releases every key grab and grabs the bindings of `config` again
+    /// If that fails, the bindings of the config in use are grabbed again before returning the error.
+    fn rebind_keys(&mut self, config: &Config) -> Result<()> {
+        self.finish_cycle(false)?;
+        // Modes may have been renamed or removed, so always come back to the default one
+        self.set_mode(DEFAULT_MODE)?;
+
+        let root = self.conn.setup().roots[self.screen_num].root;
+        self.shortcut_manager
+            .unregister_shortcuts(&self.conn, root)?;
+        if let Err(e) = self.grab_bindings(root, config) {
+            error!(
+                "Failed to grab key bindings, restoring the previous ones: {}",
+                e
+            );
+            let current = self.workspace_renderer.config().clone();
+            self.shortcut_manager
+                .unregister_shortcuts(&self.conn, root)?;
+            self.grab_bindings(root, &current)?;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Grabs the shortcuts of `config` and loads its modes and sequence timeout
+    fn grab_bindings(&mut self, root: Window, config: &Config) -> Result<()> {
+        self.shortcut_manager
+            .register_shortcuts(&self.conn, root, config.shortcuts())?;
+        self.shortcut_manager.register_modes(&config.modes);
+        self.shortcut_manager
+            .set_sequence_timeout(config.sequence_timeout());
+        self.shortcut_manager.log_summary();
+        Ok(())
+    }
+
+    /// Sends queued events (plus any focus change) to hooks and IPC subscribers
+    fn dispatch_events(&mut self) {
+        let focused = self.current_workspace().focused_window();
+        if focused != self.reported_focus {
+            self.reported_focus = focused;
+            self.events.emit(WmEvent::FocusChanged { window: focused });
+        }
+        if let Some(window) = focused
+            && self.current_workspace().is_urgent(window)
+            && let Err(e) = self.set_urgent(window, self.current_workspace_index, false)
+        {
+            error!("Failed to clear urgency of window {:?}: {}", window, e);
+        }
+
+        let events = self.events.drain();
+        for event in &events {
+            self.run_hook(event);
+        }
+        if let Some(server) = &mut self.ipc_server {
+            server.broadcast(&events);
+        }
+    }
+
+    /// Runs the `[hooks]` command for an event, if one is configured
+    fn run_hook(&self, event: &WmEvent) {
+        let Some(command) = self.workspace_renderer.config().hooks().command(event) else {
+            return;
+        };
+        let workspace = event
+            .workspace()
+            .filter(|&index| index < self.workspaces.len())
+            .unwrap_or(self.current_workspace_index);
+
+        let mut env = event.hook_env();
+        let name = self.workspaces[workspace].name().unwrap_or_default();
+        env.push(("RUSTILE_WORKSPACE_NAME", name.to_string()));
+        if let WmEvent::WorkspaceSwitched { from, .. } = event {
+            let name = self.workspaces.get(*from).and_then(|w| w.name());
+            env.push((
+                "RUSTILE_PREVIOUS_WORKSPACE_NAME",
+                name.unwrap_or_default().to_string(),
+            ));
+        }
+        if let Some(window) = event.window() {
+            // The window may already be gone; the hook still gets its id
+            let info = ewmh::window_info(&self.conn, &self.atoms, window).unwrap_or_default();
+            env.push(("RUSTILE_WINDOW", window.to_string()));
+            env.push(("RUSTILE_WINDOW_CLASS", info.class));
+            env.push(("RUSTILE_WINDOW_INSTANCE", info.instance));
+            env.push(("RUSTILE_WINDOW_TITLE", info.title));
+        }
+
+        let context = SpawnContext {
+            workspace,
+            focused_window: self.current_workspace().focused_window(),
+            user_time: 0,
+            env,
+        };
+        let shell = self.workspace_renderer.config().shell();
+        if let Err(e) = spawn::spawn(shell, command, context, &spawn::log_path()) {
+            error!("Failed to run {} hook: {}", event.kind(), e);
+        }
+    }
+
+    /// Blocks until the X11 connection, a signal, the IPC socket or an IPC client is readable,
+    /// a subscriber with queued events is writable, or a pending key sequence, a silent
+    /// IPC client or a closing subscriber times out
+    /// Returns true if IPC connections, requests or subscribers need handling
+    fn wait_for_input(&self, x11_fd: &OwnedFd) -> Result<bool> {
+        let mut fds = vec![
+            PollFd::new(x11_fd, PollFlags::IN),
+            PollFd::new(&self.signals, PollFlags::IN),
+        ];
+        // The listener, clients still sending their request line, then subscribers with
+        // events the socket could not take yet
+        if let Some(server) = &self.ipc_server {
+            fds.push(PollFd::new(server, PollFlags::IN));
+            fds.extend(
+                server
+                    .client_fds()
+                    .map(|fd| PollFd::from_borrowed_fd(fd, PollFlags::IN)),
+            );
+            fds.extend(
+                server
+                    .pending_output_fds()
+                    .map(|fd| PollFd::from_borrowed_fd(fd, PollFlags::OUT)),
+            );
+        }
+
+        let ipc_deadline = self.ipc_server.as_ref().and_then(IpcServer::next_deadline);
+        let timeout = self
+            .shortcut_manager
+            .sequence_deadline()
+            .into_iter()
+            .chain(ipc_deadline)
+            .min()
+            .map(|deadline| Timespec::try_from(deadline.saturating_duration_since(Instant::now())))
+            .transpose()?;
+
+        match poll(&mut fds, timeout.as_ref()) {
+            Ok(_) => {}
+            Err(rustix::io::Errno::INTR) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+
+        let ipc_ready = fds[2..].iter().any(|fd| !fd.revents().is_empty());
+        Ok(ipc_ready || ipc_deadline.is_some_and(|deadline| deadline <= Instant::now()))
+    }
+
+    /// Sends queued events to subscribers and executes commands received over the IPC socket
+    fn handle_ipc_requests(&mut self) {
+        let requests = match &mut self.ipc_server {
+            Some(server) => {
+                server.flush_subscribers();
+                server.accept_requests()
+            }
+            None => return,
+        };
+
+        for request in requests {
+            info!("IPC command: {}", request.message);
+            if request.message.is_empty() {
+                request.reply("error: empty command");
+                continue;
+            }
+
+            if let Some(subscription) = parse_subscribe(&request.message) {
+                match subscription {
+                    Ok(kinds) => {
+                        if let Some(server) = &mut self.ipc_server {
+                            server.subscribe(request, kinds);
+                        }
+                    }
+                    Err(e) => request.reply(&format!("error: {e}")),
+                }
+                continue;
+            }
+
+            if let Some(query) = Query::parse(&request.message) {
+                match self.handle_query(query) {
+                    Ok(json) => request.reply(&json),
+                    Err(e) => request.reply(&format!("error: {e}")),
+                }
+                continue;
+            }
+
+            let command = request.message.clone();
+            match self.execute_command(&command) {
+                Ok(()) => request.reply("ok"),
+                Err(e) => request.reply(&format!("error: {e}")),
+            }
+        }
+    }
+
+    /// Answers an IPC state query with single-line JSON
+    fn handle_query(&self, query: Query) -> Result<String> {
+        let json = match query {
+            Query::Workspaces => serde_json::to_string(&WorkspacesReply {
+                current: self.current_workspace_index,
+                workspaces: self
+                    .workspaces
+                    .iter()
+                    .enumerate()
+                    .map(|(index, workspace)| {
+                        WorkspaceInfo::new(index, workspace, index == self.current_workspace_index)
+                    })
+                    .collect(),
+            })?,
+            Query::Tree => {
+                let trees: Vec<WorkspaceTree> = self
+                    .workspaces
+                    .iter()
+                    .enumerate()
+                    .map(|(index, workspace)| {
+                        let geometries = self
+                            .workspace_renderer
+                            .window_geometries(&self.conn, workspace);
+                        WorkspaceTree {
+                            index,
+                            floating: workspace.floating_windows().to_vec(),
+                            root: workspace
+                                .bsp_tree()
+                                .root
+                                .as_ref()
+                                .map(|root| TreeNode::new(root, &geometries)),
+                        }
+                    })
+                    .collect();
+                serde_json::to_string(&trees)?
+            }
+            Query::Config => serde_json::to_string(self.workspace_renderer.config())?,
+            Query::Mode => serde_json::to_string(&ModeReply {
+                mode: self.shortcut_manager.active_mode(),
+            })?,
+        };
+        Ok(json)
+    }
+
     // =============================================================================
     // Event Handling
     // =============================================================================
@@ -277,11 +1355,16 @@ impl<C: Connection> WindowManager<C> {
     pub(crate) fn handle_event(&mut self, event: Event) -> Result<()> {
         match event {
             Event::KeyPress(ev) => self.handle_key_press(ev),
+            Event::KeyRelease(ev) => self.handle_key_release(ev),
             Event::MapRequest(ev) => self.handle_map_request(ev),
             Event::UnmapNotify(ev) => self.handle_unmap_notify(ev),
             Event::ConfigureRequest(ev) => self.handle_configure_request(ev),
             Event::DestroyNotify(ev) => self.handle_destroy_notify(ev),
             Event::EnterNotify(ev) => self.handle_enter_notify(ev),
+            Event::MappingNotify(ev) => self.handle_mapping_notify(ev),
+            Event::ClientMessage(ev) => self.handle_client_message(ev),
+            Event::XinputRawKeyPress(ev) => self.handle_raw_key(ev.detail, true, ev.time),
+            Event::XinputRawKeyRelease(ev) => self.handle_raw_key(ev.detail, false, ev.time),
             _ => {
                 #[cfg(debug_assertions)]
                 debug!("Unhandled event: {:#?}", event);
@@ -292,114 +1375,469 @@ impl<C: Connection> WindowManager<C> {
 
     /// Handles key press events
     fn handle_key_press(&mut self, event: KeyPressEvent) -> Result<()> {
-        let (command_opt, matched) = self.shortcut_manager.handle_key_press(&self.conn, &event)?;
-
-        if matched {
-            // Shortcut matched - allow event processing to continue (ADR-015)
-            if let Err(e) = self.conn.allow_events(Allow::ASYNC_KEYBOARD, CURRENT_TIME) {
-                error!("Failed to allow keyboard events: {}", e);
-                // Fallback: try again to prevent keyboard freeze
-                let _ = self.conn.allow_events(Allow::ASYNC_KEYBOARD, CURRENT_TIME);
-            }
-
-            if let Some(command) = command_opt {
-                info!("Shortcut pressed, executing: {}", command);
-
-                // Handle window management commands
-                match command {
-                    "focus_next" => return self.focus_next(),
-                    "focus_prev" => return self.focus_prev(),
-                    "swap_window_next" => return self.swap_window_next(),
-                    "swap_window_prev" => return self.swap_window_prev(),
-                    "destroy_window" => return self.destroy_focused_window(),
-                    "toggle_fullscreen" => return self.toggle_fullscreen(),
-                    "rotate_windows" => return self.rotate_windows(),
-                    "toggle_zoom" => return self.toggle_zoom(),
-                    "balance_tree" => return self.balance_tree(),
-                    // Workspace management commands
-                    "create_workspace" => {
-                        self.create_workspace();
-                        return Ok(());
-                    }
-                    "delete_workspace" => {
-                        self.delete_workspace();
-                        return Ok(());
-                    }
-                    "switch_workspace_next" => {
-                        self.switch_workspace_next();
-                        return Ok(());
-                    }
-                    "switch_workspace_prev" => {
-                        self.switch_workspace_prev();
-                        return Ok(());
-                    }
-                    "shutdown" => {
-                        info!("Shutting down Rustile by user request");
-                        std::process::exit(0);
-                    }
-                    _ => {
-                        let parts: Vec<&str> = command.split_whitespace().collect();
-                        if let Some(program) = parts.first() {
-                            let mut cmd = Command::new(program);
-
-                            if parts.len() > 1 {
-                                cmd.args(&parts[1..]);
-                            }
-
-                            match cmd.spawn() {
-                                Ok(_) => info!("Successfully launched: {}", command),
-                                Err(e) => error!("Failed to launch {}: {}", command, e),
-                            }
-                        }
-                    }
+        let chain = match self.shortcut_manager.handle_key_press(&self.conn, &event)? {
+            KeyAction::Run(chain) => Some(chain.clone()),
+            KeyAction::Wait => {
+                // Key sequence in progress - thaw for exactly one more key event
+                self.allow_keyboard(Allow::SYNC_KEYBOARD);
+                return Ok(());
+            }
+            KeyAction::Ignore => None,
+            KeyAction::Replay => {
+                // No shortcut matched - replay event to focused application (ADR-015)
+                self.allow_keyboard(Allow::REPLAY_KEYBOARD);
+                return Ok(());
+            }
+            KeyAction::Cancel => {
+                info!("Window cycle cancelled");
+                return self.finish_cycle(false);
+            }
+        };
+
+        // Shortcut matched - allow event processing to continue (ADR-015)
+        self.allow_keyboard(Allow::ASYNC_KEYBOARD);
+
+        if let Some(chain) = chain {
+            info!("Shortcut pressed, executing: {}", chain);
+            return self.run_user_chain(&chain, event.time);
+        }
+        Ok(())
+    }
+
+    /// Re-grabs all bindings when the keyboard layout changes (setxkbmap, a new keyboard)
+    fn handle_mapping_notify(&mut self, event: MappingNotifyEvent) -> Result<()> {
+        if event.request == Mapping::POINTER {
+            return Ok(());
+        }
+
+        info!("Keyboard mapping changed, re-grabbing shortcuts");
+        self.shortcut_manager.refresh_mapping(&self.conn)?;
+        let config = self.workspace_renderer.config().clone();
+        self.rebind_keys(&config)
+    }
+
+    /// Key releases only reach us while a key sequence or a held binding has the keyboard
+    fn handle_key_release(&mut self, event: KeyReleaseEvent) -> Result<()> {
+        if self.shortcut_manager.is_sequence_pending() {
+            self.allow_keyboard(Allow::SYNC_KEYBOARD);
+        }
+        if self.shortcut_manager.hold_released(&event) {
+            return self.finish_cycle(true);
+        }
+        Ok(())
+    }
+
+    /// Tracks pressed keys and runs release bindings
+    fn handle_raw_key(&mut self, keycode: u32, pressed: bool, time: Timestamp) -> Result<()> {
+        let chain = self
+            .shortcut_manager
+            .handle_raw_key(keycode, pressed)
+            .cloned();
+        if let Some(chain) = chain {
+            info!("Key released, executing: {}", chain);
+            return self.run_user_chain(&chain, time);
+        }
+        Ok(())
+    }
+
+    /// Runs a chain bound to a key, noting the key's time and whether it moved focus
+    fn run_user_chain(&mut self, chain: &CommandChain, time: Timestamp) -> Result<()> {
+        self.last_user_time = time;
+        let focused = self.current_workspace().focused_window();
+        let result = self.run_chain(chain);
+        if self.current_workspace().focused_window() != focused {
+            self.focus_time = time;
+        }
+        result
+    }
+
+    /// Releases the frozen keyboard, falling back to ASYNC_KEYBOARD to prevent a keyboard freeze
+    fn allow_keyboard(&self, mode: Allow) {
+        if let Err(e) = self.conn.allow_events(mode, CURRENT_TIME) {
+            error!("Failed to allow keyboard events ({:?}): {}", mode, e);
+            let _ = self.conn.allow_events(Allow::ASYNC_KEYBOARD, CURRENT_TIME);
+        }
+    }
+
+    /// Parses and executes a command line received over the IPC socket
+    pub(crate) fn execute_command(&mut self, command: &str) -> Result<()> {
+        let chain = CommandChain::parse(command)?;
+        self.run_chain(&chain)
+    }
+
+    /// Runs every action of a chain, then renders and flushes once
+    /// Stops at the first failing action; what already ran is still rendered.
+    pub(crate) fn run_chain(&mut self, chain: &CommandChain) -> Result<()> {
+        self.workspace_renderer.begin_batch();
+        let result = chain
+            .0
+            .iter()
+            .try_for_each(|action| self.run_action(action));
+
+        if self.workspace_renderer.end_batch() {
+            self.apply_current_workspace()?;
+        }
+        self.conn.flush()?;
+        result
+    }
+
+    /// Runs one action, evaluating its condition against the current workspace
+    fn run_action(&mut self, action: &Action) -> Result<()> {
+        match action {
+            Action::Run(command) => self.run_command(command.clone()),
+            Action::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                if self.check_condition(condition) {
+                    self.run_command(then.clone())
+                } else if let Some(otherwise) = otherwise {
+                    self.run_command(otherwise.clone())
+                } else {
+                    Ok(())
                 }
             }
-        } else {
-            // No shortcut matched - replay event to focused application (ADR-015)
-            if let Err(e) = self.conn.allow_events(Allow::REPLAY_KEYBOARD, CURRENT_TIME) {
-                error!("Failed to replay key event: {}", e);
-                // Fallback: try ASYNC_KEYBOARD to prevent keyboard freeze
-                let _ = self.conn.allow_events(Allow::ASYNC_KEYBOARD, CURRENT_TIME);
+        }
+    }
+
+    /// Evaluates a condition of a conditional action
+    fn check_condition(&self, condition: &Condition) -> bool {
+        let workspace = self.current_workspace();
+        let focused = workspace.focused_window();
+        match condition {
+            Condition::Fullscreen => focused.is_some() && workspace.fullscreen_window() == focused,
+            Condition::Zoomed => focused.is_some() && workspace.zoomed_window() == focused,
+            Condition::Focused => focused.is_some(),
+            Condition::Empty => workspace.get_all_windows().is_empty(),
+            Condition::Not(condition) => !self.check_condition(condition),
+        }
+    }
+
+    /// Runs a parsed command against the window manager state
+    pub(crate) fn run_command(&mut self, command: WmCommand) -> Result<()> {
+        match command {
+            WmCommand::FocusNext => self.focus_next(),
+            WmCommand::FocusPrev => self.focus_prev(),
+            WmCommand::FocusLast => self.focus_last(),
+            WmCommand::CycleNext => self.cycle_windows(true),
+            WmCommand::CyclePrev => self.cycle_windows(false),
+            WmCommand::SwapWindowNext => self.swap_window_next(),
+            WmCommand::SwapWindowPrev => self.swap_window_prev(),
+            WmCommand::DestroyWindow => self.destroy_focused_window(),
+            WmCommand::ToggleFullscreen => self.toggle_fullscreen(),
+            WmCommand::RotateWindows => self.rotate_windows(),
+            WmCommand::ToggleZoom => self.toggle_zoom(),
+            WmCommand::ToggleSticky => self.toggle_sticky(),
+            WmCommand::BalanceTree => self.balance_tree(),
+            WmCommand::Resize(direction, pixels) => self.resize_window(direction, pixels),
+            // Workspace management commands
+            WmCommand::CreateWorkspace(name) => {
+                self.create_workspace(name);
+                Ok(())
+            }
+            WmCommand::DeleteWorkspace => {
+                self.delete_workspace();
+                Ok(())
             }
+            WmCommand::SwitchWorkspaceNext => {
+                self.switch_workspace_next();
+                Ok(())
+            }
+            WmCommand::SwitchWorkspacePrev => {
+                self.switch_workspace_prev();
+                Ok(())
+            }
+            WmCommand::SwitchWorkspace(target) => {
+                let index = self.resolve_workspace(&target)?;
+                self.switch_to_workspace(index);
+                Ok(())
+            }
+            WmCommand::MoveWindowToWorkspace(target) => self.move_window_to_workspace(&target),
+            WmCommand::MoveToScratchpad => self.move_to_scratchpad(),
+            WmCommand::ScratchpadShow(class) => self.scratchpad_show(class.as_deref()),
+            WmCommand::Mode(mode) => self.set_mode(&mode),
+            WmCommand::ReloadConfig => self.reload_config(),
+            WmCommand::Restart => {
+                self.restart_requested = true;
+                Ok(())
+            }
+            WmCommand::Shutdown => {
+                info!("Shutting down Rustile by user request");
+                self.shutdown_requested = true;
+                Ok(())
+            }
+            WmCommand::Exec(command) => self.spawn(&command),
+        }
+    }
+
+    /// Switches the key binding mode, which also changes the focused border color
+    fn set_mode(&mut self, mode: &str) -> Result<()> {
+        if mode == self.shortcut_manager.active_mode() {
+            return Ok(());
         }
+
+        let root = self.conn.setup().roots[self.screen_num].root;
+        self.shortcut_manager.enter_mode(&self.conn, root, mode)?;
+        Self::publish_mode(&self.conn, &self.atoms, root, mode)?;
+        self.workspace_renderer.set_mode(mode);
+        self.events.emit(WmEvent::ModeChanged {
+            mode: mode.to_string(),
+        });
+        self.apply_current_workspace()
+    }
+
+    /// Stores the mode name in the `_RUSTILE_MODE` root window property for status bars
+    fn publish_mode(conn: &C, atoms: &ewmh::Atoms, root: Window, mode: &str) -> Result<()> {
+        conn.change_property8(
+            PropMode::REPLACE,
+            root,
+            atoms._RUSTILE_MODE,
+            atoms.UTF8_STRING,
+            mode.as_bytes(),
+        )?;
         Ok(())
     }
 
-    /// Handles window map requests
+    /// Launches a shell command with the current workspace and focus exported
+    fn spawn(&self, command: &str) -> Result<()> {
+        self.spawn_on(command, self.current_workspace_index)
+            .map(|_| ())
+    }
+
+    /// Launches a shell command for a workspace, returning the child's pid
+    fn spawn_on(&self, command: &str, workspace: usize) -> Result<u32> {
+        let context = SpawnContext {
+            workspace,
+            focused_window: self.workspaces[workspace].focused_window(),
+            user_time: self.last_user_time,
+            env: Vec::new(),
+        };
+        let shell = self.workspace_renderer.config().shell();
+        spawn::spawn(shell, command, context, &spawn::log_path()).inspect_err(|e| error!("{}", e))
+    }
+
+    /// Handles window map requests: window rules decide where the window goes and how
     fn handle_map_request(&mut self, event: MapRequestEvent) -> Result<()> {
         let window = event.window;
-        info!("Mapping window: {:?}", window);
 
-        // Check if window is already managed in current workspace
-        if self.current_workspace().has_window(window) {
+        // Check if window is already managed (hidden workspaces included)
+        if self.workspaces.iter().any(|w| w.has_window(window)) {
             info!(
                 "Window {:?} is already managed, ignoring duplicate MapRequest",
                 window
             );
             return Ok(());
         }
+        // A hidden scratchpad window mapping itself again is managed like a new window
+        if self.scratchpad.contains(&window) {
+            self.scratchpad.retain(|&w| w != window);
+            self.intentionally_unmapped.remove(&window);
+        }
 
-        self.conn.map_window(window)?;
+        // Remember the client's own border width so teardown can restore it
+        let geometry = self.conn.get_geometry(window)?.reply().ok();
+        if let Some(geometry) = &geometry {
+            self.original_borders
+                .entry(window)
+                .or_insert(geometry.border_width);
+        }
+
+        let info = ewmh::window_info(&self.conn, &self.atoms, window)?;
+        let mut actions = rules::evaluate(self.workspace_renderer.config().rules(), &info);
+        // The window of a launched named scratchpad is shown like one
+        let now = Instant::now();
+        self.scratchpad_launches
+            .retain(|launch| launch.expires > now);
+        if let Some(index) = self
+            .scratchpad_launches
+            .iter()
+            .position(|launch| info.has_class(&launch.class))
+        {
+            self.scratchpad_launches.remove(index);
+            self.scratchpad.push(window);
+            actions.workspace = Some(WorkspaceTarget::Index(self.current_workspace_index));
+            actions.floating = true;
+            actions.geometry = Some(self.scratchpad_geometry(&info));
+            actions.fullscreen = false;
+            actions.focus = Some(true);
+        }
+        // Clients may ask to be sticky before they are mapped
+        let sticky_state = self.atoms._NET_WM_STATE_STICKY;
+        if !actions.sticky && ewmh::has_wm_state(&self.conn, &self.atoms, window, sticky_state) {
+            info!("Window {:?} is mapped sticky", window);
+            actions.sticky = true;
+        }
+        // Sticky windows float on the workspace in view and follow it
+        if actions.sticky {
+            actions.workspace = Some(WorkspaceTarget::Index(self.current_workspace_index));
+            actions.floating = true;
+            actions.fullscreen = false;
+        }
+        if actions != RuleActions::default() {
+            info!(
+                "Window rules for {:?} ({} / {}): {:?}",
+                window, info.class, info.title, actions
+            );
+        }
+        // A rule's workspace wins over the one a program was autostarted for
+        let target = match actions.workspace.clone() {
+            Some(target) => Some(target),
+            None => self.launch_workspace(window)?,
+        };
+        let workspace = match target.as_ref().map(|t| self.resolve_workspace(t)) {
+            Some(Ok(index)) => index,
+            Some(Err(e)) => {
+                error!("Window rule for {:?}: {}", window, e);
+                self.current_workspace_index
+            }
+            None => self.current_workspace_index,
+        };
+
+        let screen = &self.conn.setup().roots[self.screen_num];
+        let (screen_width, screen_height) = (screen.width_in_pixels, screen.height_in_pixels);
+        let target = &mut self.workspaces[workspace];
+        if actions.floating {
+            let geometry = actions.geometry.unwrap_or(Geometry {
+                width: geometry.as_ref().map_or(640, |g| u32::from(g.width)),
+                height: geometry.as_ref().map_or(480, |g| u32::from(g.height)),
+                position: None,
+            });
+            target.add_floating(geometry.place(window, screen_width, screen_height));
+        } else {
+            target.insert_window(window, actions.split_ratio.unwrap_or(0.5), actions.insert);
+        }
+        if let Some(width) = actions.border_width {
+            target.set_border_width(window, width);
+        }
+        if actions.fullscreen {
+            if let Some(previous) = target.fullscreen_window() {
+                self.events.emit(WmEvent::FullscreenToggled {
+                    window: previous,
+                    enabled: false,
+                });
+            }
+            self.workspaces[workspace].set_fullscreen_window(Some(window));
+            self.events.emit(WmEvent::FullscreenToggled {
+                window,
+                enabled: true,
+            });
+        }
+        if actions.sticky {
+            self.sticky.insert(window);
+            self.publish_sticky(window, true)?;
+            self.events.emit(WmEvent::StickyToggled {
+                window,
+                enabled: true,
+            });
+        }
+        self.events
+            .emit(WmEvent::WindowMapped { window, workspace });
+        self.focus_new_window(window, workspace, actions.focus)?;
 
-        // Add to current workspace
-        self.current_workspace_mut().add_window(window);
-        self.current_workspace_mut()
-            .set_focused_window(Some(window));
+        // Windows of hidden workspaces are mapped when their workspace is shown
+        if workspace != self.current_workspace_index {
+            return Ok(());
+        }
+        self.conn.map_window(window)?;
 
         // Render the workspace
-        {
+        if actions.fullscreen {
+            self.apply_fullscreen_layout(window)
+        } else {
             let workspace = &self.workspaces[self.current_workspace_index];
             self.workspace_renderer
-                .apply_workspace(&mut self.conn, workspace)?;
+                .apply_workspace(&mut self.conn, workspace)
+        }
+    }
+
+    /// Focuses a new window unless `focus_new_windows` or its `_NET_WM_USER_TIME` say it
+    /// would interrupt the user, in which case it is marked urgent instead
+    /// A window rule's `focus` setting decides on its own
+    fn focus_new_window(
+        &mut self,
+        window: Window,
+        workspace: usize,
+        rule: Option<bool>,
+    ) -> Result<()> {
+        match rule {
+            Some(true) => {}
+            Some(false) => {
+                info!("Window rule keeps focus away from window {:?}", window);
+                return Ok(());
+            }
+            None => return self.focus_new_window_by_policy(window, workspace),
+        }
+        self.workspaces[workspace].set_focused_window(Some(window));
+        Ok(())
+    }
+
+    /// Applies `focus_new_windows` and the window's user time to a new window
+    fn focus_new_window_by_policy(&mut self, window: Window, workspace: usize) -> Result<()> {
+        let policy = self.workspace_renderer.config().focus_new_windows();
+        if !policy.allows(workspace == self.current_workspace_index) {
+            return self.set_urgent(window, workspace, true);
+        }
+
+        if let Some(time) = ewmh::user_time(&self.conn, &self.atoms, window)
+            && ewmh::is_older(time, self.focus_time)
+        {
+            info!(
+                "Window {:?} opened without recent user input, not focusing it",
+                window
+            );
+            return self.set_urgent(window, workspace, true);
         }
 
+        self.workspaces[workspace].set_focused_window(Some(window));
         Ok(())
     }
 
+    /// Marks or clears a window as demanding attention, mirrored in its `_NET_WM_STATE`
+    fn set_urgent(&mut self, window: Window, workspace: usize, urgent: bool) -> Result<()> {
+        if !self.workspaces[workspace].set_urgent(window, urgent) {
+            return Ok(());
+        }
+        ewmh::set_wm_state(
+            &self.conn,
+            &self.atoms,
+            window,
+            self.atoms._NET_WM_STATE_DEMANDS_ATTENTION,
+            urgent,
+        )?;
+        self.events.emit(WmEvent::UrgencyChanged {
+            window,
+            workspace,
+            urgent,
+        });
+        Ok(())
+    }
+
+    /// Handles `_NET_WM_STATE` requests from clients; `_NET_WM_STATE_STICKY` is supported
+    fn handle_client_message(&mut self, event: ClientMessageEvent) -> Result<()> {
+        if event.format != 32 || event.type_ != self.atoms._NET_WM_STATE {
+            return Ok(());
+        }
+        let data = event.data.as_data32();
+        let sticky_state = self.atoms._NET_WM_STATE_STICKY;
+        if data[1] != sticky_state && data[2] != sticky_state {
+            return Ok(());
+        }
+
+        let window = event.window;
+        let Some(sticky) = ewmh::state_action(data[0], self.sticky.contains(&window)) else {
+            info!("Ignoring _NET_WM_STATE action {} for {:?}", data[0], window);
+            return Ok(());
+        };
+        info!("Window {:?} asks to be sticky: {}", window, sticky);
+        self.set_sticky(window, sticky)
+    }
+
     /// Handles window unmap notifications
     fn handle_unmap_notify(&mut self, event: UnmapNotifyEvent) -> Result<()> {
         let window = event.window;
-        info!("Unmapping window: {:?}", window);
+        #[cfg(debug_assertions)]
+        debug!("Unmap notify for window: {:?}", window);
 
         // Check intentionally_unmapped from WindowManager (workspace switches)
         if self.intentionally_unmapped.contains(&window) {
@@ -407,27 +1845,20 @@ impl<C: Connection> WindowManager<C> {
             return Ok(());
         }
 
-        info!(
-            "Window {:?} closed by user, removing from management",
-            window
-        );
+        self.scratchpad.retain(|&w| w != window);
+        self.sticky.remove(&window);
 
         // Remove from all workspaces (window could be in any workspace)
+        let mut was_managed = false;
+        let policy = self.workspace_renderer.config().focus_on_close();
         for workspace in &mut self.workspaces {
             if workspace.has_window(window) {
-                workspace.remove_window(window);
+                workspace.remove_window_with_focus(window, policy);
+                was_managed = true;
             }
         }
-
-        // Update focus in current workspace
-        if self.current_workspace().focused_window() == Some(window) {
-            let next_focus = self.current_workspace().get_first_window();
-            if let Some(next_focus) = next_focus {
-                self.current_workspace_mut()
-                    .set_focused_window(Some(next_focus));
-            } else {
-                self.current_workspace_mut().clear_focus();
-            }
+        if was_managed {
+            self.events.emit(WmEvent::WindowUnmapped { window });
         }
 
         // Render the workspace
@@ -457,23 +1888,49 @@ impl<C: Connection> WindowManager<C> {
         let values = ConfigureWindowAux::from_configure_request(&event);
         self.conn.configure_window(event.window, &values)?;
 
+        // Floating windows may move and resize themselves, so keep what they asked for
+        for workspace in &mut self.workspaces {
+            if let Some(geometry) = workspace.floating_geometry_mut(event.window) {
+                if event.value_mask.contains(ConfigWindow::X) {
+                    geometry.x = i32::from(event.x);
+                }
+                if event.value_mask.contains(ConfigWindow::Y) {
+                    geometry.y = i32::from(event.y);
+                }
+                if event.value_mask.contains(ConfigWindow::WIDTH) {
+                    geometry.width = u32::from(event.width);
+                }
+                if event.value_mask.contains(ConfigWindow::HEIGHT) {
+                    geometry.height = u32::from(event.height);
+                }
+            }
+        }
+
         Ok(())
     }
 
     /// Handles window destroy notifications
     fn handle_destroy_notify(&mut self, event: DestroyNotifyEvent) -> Result<()> {
         let window = event.window;
-        info!("Window destroyed: {:?}", window);
 
         // Remove from intentionally_unmapped (WindowManager)
         self.intentionally_unmapped.remove(&window);
+        self.original_borders.remove(&window);
+        self.scratchpad.retain(|&w| w != window);
+        self.sticky.remove(&window);
 
         // Remove from all workspaces
+        let mut was_managed = false;
+        let policy = self.workspace_renderer.config().focus_on_close();
         for workspace in &mut self.workspaces {
             if workspace.has_window(window) {
-                workspace.remove_window(window);
+                workspace.remove_window_with_focus(window, policy);
+                was_managed = true;
             }
         }
+        if was_managed {
+            self.events.emit(WmEvent::WindowDestroyed { window });
+        }
 
         // Clear fullscreen in current workspace
         if self.current_workspace().fullscreen_window() == Some(window) {
@@ -481,17 +1938,6 @@ impl<C: Connection> WindowManager<C> {
             self.current_workspace_mut().clear_fullscreen();
         }
 
-        // Update focus in current workspace
-        if self.current_workspace().focused_window() == Some(window) {
-            let next_focus = self.current_workspace().get_first_window();
-            if let Some(next_focus) = next_focus {
-                self.current_workspace_mut()
-                    .set_focused_window(Some(next_focus));
-            } else {
-                self.current_workspace_mut().clear_focus();
-            }
-        }
-
         // Render the workspace
         {
             let workspace = &self.workspaces[self.current_workspace_index];
@@ -510,6 +1956,9 @@ impl<C: Connection> WindowManager<C> {
 
         // Update current workspace
         if self.current_workspace().has_window(window) {
+            if self.current_workspace().focused_window() != Some(window) {
+                self.focus_time = event.time;
+            }
             self.current_workspace_mut()
                 .set_focused_window(Some(window));
 
@@ -542,6 +1991,71 @@ impl<C: Connection> WindowManager<C> {
         self.workspace_renderer
             .focus_prev(&mut self.conn, workspace)
     }
+
+    /// Focuses the previously focused window of the current workspace
+    pub fn focus_last(&mut self) -> Result<()> {
+        match self.current_workspace().previous_window() {
+            Some(window) => self.focus_window(window),
+            None => Ok(()),
+        }
+    }
+
+    /// Steps through the recently used windows, previewing the selection with the focused
+    /// border; focus (and the MRU order) changes once the binding's modifiers are released
+    pub fn cycle_windows(&mut self, forward: bool) -> Result<()> {
+        if self.cycle.is_none() {
+            let mut cycle = WindowCycle::new(self.current_workspace().mru_windows());
+            if cycle.windows.len() < 2 {
+                return Ok(());
+            }
+
+            let root = self.conn.setup().roots[self.screen_num].root;
+            if !self.shortcut_manager.begin_hold(&self.conn, root)? {
+                // Nothing held (IPC, or the keys were already let go): switch right away
+                let window = cycle.step(forward);
+                return self.focus_window(window);
+            }
+            self.cycle = Some(cycle);
+        }
+
+        let Some(cycle) = self.cycle.as_mut() else {
+            return Ok(());
+        };
+        let window = cycle.step(forward);
+        self.workspace_renderer.set_preview(Some(window));
+        self.apply_current_workspace()
+    }
+
+    /// Ends the window cycle, focusing the selected window if `commit` is set
+    fn finish_cycle(&mut self, commit: bool) -> Result<()> {
+        let Some(cycle) = self.cycle.take() else {
+            return Ok(());
+        };
+        self.shortcut_manager.end_hold(&self.conn)?;
+        self.workspace_renderer.set_preview(None);
+
+        let selected = cycle.selected();
+        if commit && self.current_workspace().has_window(selected) {
+            self.focus_window(selected)
+        } else {
+            self.apply_current_workspace()
+        }
+    }
+
+    /// Focuses a window of the current workspace, leaving fullscreen if it is another window
+    fn focus_window(&mut self, window: Window) -> Result<()> {
+        let workspace = self.current_workspace_mut();
+        if workspace
+            .fullscreen_window()
+            .is_some_and(|fullscreen| fullscreen != window)
+        {
+            info!("Exiting fullscreen mode to focus different window");
+            workspace.clear_fullscreen();
+        }
+        workspace.set_focused_window(Some(window));
+        info!("Focused window: {:?}", window);
+        self.apply_current_workspace()
+    }
 }
 
 impl<C: Connection> WindowManager<C> {
@@ -555,15 +2069,17 @@ impl<C: Connection> WindowManager<C> {
     /// Swaps focused window with next window in BSP order
     pub fn swap_window_next(&mut self) -> Result<()> {
         let workspace = &mut self.workspaces[self.current_workspace_index];
+        let index = self.current_workspace_index;
         self.workspace_renderer
-            .swap_window_next(&mut self.conn, workspace)
+            .swap_window_next(&mut self.conn, workspace, index, &mut self.events)
     }
 
     /// Swaps focused window with previous window in BSP order
     pub fn swap_window_prev(&mut self) -> Result<()> {
         let workspace = &mut self.workspaces[self.current_workspace_index];
+        let index = self.current_workspace_index;
         self.workspace_renderer
-            .swap_window_prev(&mut self.conn, workspace)
+            .swap_window_prev(&mut self.conn, workspace, index, &mut self.events)
     }
 
     /// Toggles fullscreen mode for focused window
@@ -580,16 +2096,7 @@ impl<C: Connection> WindowManager<C> {
         if let Some(fullscreen) = self.current_workspace().fullscreen_window() {
             if fullscreen == focused {
                 // Exit fullscreen mode
-                info!("Exiting fullscreen mode for window {:?}", focused);
-                self.current_workspace_mut().set_fullscreen_window(None);
-
-                // Map all windows in the current workspace
-                for window in self.current_workspace().get_all_windows() {
-                    self.intentionally_unmapped.remove(&window);
-                    if let Err(e) = self.conn.map_window(window) {
-                        error!("Failed to map window {:?}: {}", window, e);
-                    }
-                }
+                self.leave_fullscreen();
 
                 // Render normal layout
                 {
@@ -605,13 +2112,24 @@ impl<C: Connection> WindowManager<C> {
                 );
                 self.current_workspace_mut()
                     .set_fullscreen_window(Some(focused));
+                self.events.emit(WmEvent::FullscreenToggled {
+                    window: fullscreen,
+                    enabled: false,
+                });
+                self.events.emit(WmEvent::FullscreenToggled {
+                    window: focused,
+                    enabled: true,
+                });
                 self.apply_fullscreen_layout(focused)?;
             }
         } else {
             // Enter fullscreen mode
-            info!("Entering fullscreen mode for window {:?}", focused);
             self.current_workspace_mut()
                 .set_fullscreen_window(Some(focused));
+            self.events.emit(WmEvent::FullscreenToggled {
+                window: focused,
+                enabled: true,
+            });
             self.apply_fullscreen_layout(focused)?;
         }
 
@@ -620,6 +2138,10 @@ impl<C: Connection> WindowManager<C> {
 
     /// Applies fullscreen layout for a window
     fn apply_fullscreen_layout(&mut self, fullscreen: Window) -> Result<()> {
+        if self.workspace_renderer.defer_render() {
+            return Ok(());
+        }
+
         let setup = self.conn.setup();
         let screen = &setup.roots[self.screen_num];
 
@@ -654,15 +2176,30 @@ impl<C: Connection> WindowManager<C> {
     /// Rotates focused window by flipping parent split direction
     pub fn rotate_windows(&mut self) -> Result<()> {
         let workspace = &mut self.workspaces[self.current_workspace_index];
+        let index = self.current_workspace_index;
         self.workspace_renderer
-            .rotate_windows(&mut self.conn, workspace)
+            .rotate_windows(&mut self.conn, workspace, index, &mut self.events)
     }
 
     /// Toggles zoom for the focused window
     pub fn toggle_zoom(&mut self) -> Result<()> {
         let workspace = &mut self.workspaces[self.current_workspace_index];
         self.workspace_renderer
-            .toggle_zoom(&mut self.conn, workspace)
+            .toggle_zoom(&mut self.conn, workspace, &mut self.events)
+    }
+
+    /// Moves the focused window's edge in the given direction
+    pub fn resize_window(&mut self, direction: Direction, pixels: i32) -> Result<()> {
+        let workspace = &mut self.workspaces[self.current_workspace_index];
+        let index = self.current_workspace_index;
+        self.workspace_renderer.resize_window(
+            &mut self.conn,
+            workspace,
+            direction,
+            pixels,
+            index,
+            &mut self.events,
+        )
     }
 
     /// Balances the BSP tree by calculating optimal split ratios based on window count
@@ -672,8 +2209,9 @@ impl<C: Connection> WindowManager<C> {
     /// ensuring all windows receive equal screen area.
     pub fn balance_tree(&mut self) -> Result<()> {
         let workspace = &mut self.workspaces[self.current_workspace_index];
+        let index = self.current_workspace_index;
         self.workspace_renderer
-            .balance_tree(&mut self.conn, workspace)
+            .balance_tree(&mut self.conn, workspace, index, &mut self.events)
     }
 }
 
@@ -1384,4 +2922,31 @@ mod tests {
         test_swap_window_next_logic(&mut windows, Some(5));
         assert_eq!(windows, vec![5, 2, 3, 4, 1]);
     }
+
+    #[test]
+    fn test_scratchpad_windows_on() {
+        let mut workspace = Workspace::new();
+        workspace.add_window(10);
+        workspace.add_floating(WindowGeometry {
+            window: 20,
+            x: 0,
+            y: 0,
+            width: 400,
+            height: 300,
+        });
+
+        // 20 is a shown scratchpad window, 30 a hidden one; 10 is not in the scratchpad
+        assert_eq!(scratchpad_windows_on(&workspace, &[20, 30]), [20]);
+        assert!(scratchpad_windows_on(&workspace, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_window_cycle_steps() {
+        let mut cycle = WindowCycle::new(vec![30, 10, 20]);
+        assert_eq!(cycle.selected(), 30);
+        assert_eq!(cycle.step(true), 10);
+        assert_eq!(cycle.step(true), 20);
+        assert_eq!(cycle.step(true), 30);
+        assert_eq!(cycle.step(false), 20);
+    }
 }
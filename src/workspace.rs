@@ -1,35 +1,127 @@
 //! Workspace state management
 
-use crate::bsp::BspTree;
+use crate::bsp::{BspTree, Direction};
+use crate::config::FocusOnClose;
+use crate::workspace_renderer::WindowGeometry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use x11rb::protocol::xproto::Window;
 
 /// Represents a single workspace with independent window layout state
+#[derive(Serialize, Deserialize)]
 pub struct Workspace {
+    name: Option<String>,
     bsp_tree: BspTree,
     focused_window: Option<Window>,
     fullscreen_window: Option<Window>,
     zoomed_window: Option<Window>,
+    /// Windows in the order they were last focused, most recent first
+    #[serde(default)]
+    focus_history: Vec<Window>,
+    /// Windows demanding attention, e.g. opened without taking focus
+    #[serde(default)]
+    urgent_windows: Vec<Window>,
+    /// Windows floating above the tiled ones, bottom to top
+    #[serde(default)]
+    floating: Vec<WindowGeometry>,
+    /// Border widths set by window rules, overriding `layout.border_width`
+    #[serde(default)]
+    border_widths: HashMap<Window, u32>,
 }
 
 impl Workspace {
     /// Creates a new empty workspace
     pub fn new() -> Self {
         Self {
+            name: None,
             bsp_tree: BspTree::new(),
             focused_window: None,
             fullscreen_window: None,
             zoomed_window: None,
+            focus_history: Vec::new(),
+            urgent_windows: Vec::new(),
+            floating: Vec::new(),
+            border_widths: HashMap::new(),
         }
     }
 
+    /// Creates a new empty workspace with a name
+    pub fn with_name(name: String) -> Self {
+        Self {
+            name: Some(name),
+            ..Self::new()
+        }
+    }
+
+    /// Gets the workspace name, if it has one
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Gets the focused window
     pub fn focused_window(&self) -> Option<Window> {
         self.focused_window
     }
 
-    /// Sets the focused window
+    /// Sets the focused window and moves it to the front of the focus history
+    /// A floating window is also raised to the top of the floating windows
     pub fn set_focused_window(&mut self, window: Option<Window>) {
         self.focused_window = window;
+        if let Some(window) = window {
+            self.focus_history.retain(|&w| w != window);
+            self.focus_history.insert(0, window);
+            if let Some(index) = self.floating.iter().position(|g| g.window == window) {
+                let geometry = self.floating.remove(index);
+                self.floating.push(geometry);
+            }
+        }
+    }
+
+    /// Gets the most recently focused window other than the focused one
+    pub fn previous_window(&self) -> Option<Window> {
+        self.mru_windows()
+            .into_iter()
+            .find(|&window| Some(window) != self.focused_window)
+    }
+
+    /// Gets every window, most recently focused first; never focused ones come last in layout order
+    pub fn mru_windows(&self) -> Vec<Window> {
+        let windows = self.get_all_windows();
+        let mut mru: Vec<Window> = self
+            .focus_history
+            .iter()
+            .copied()
+            .filter(|window| windows.contains(window))
+            .collect();
+        let unfocused: Vec<Window> = windows
+            .into_iter()
+            .filter(|window| !mru.contains(window))
+            .collect();
+        mru.extend(unfocused);
+        mru
+    }
+
+    /// Checks if a window demands attention
+    pub fn is_urgent(&self, window: Window) -> bool {
+        self.urgent_windows.contains(&window)
+    }
+
+    /// Gets the windows demanding attention, oldest first
+    pub fn urgent_windows(&self) -> &[Window] {
+        &self.urgent_windows
+    }
+
+    /// Marks or clears a window as demanding attention, returning true if that changed anything
+    pub fn set_urgent(&mut self, window: Window, urgent: bool) -> bool {
+        if self.is_urgent(window) == urgent {
+            return false;
+        }
+        if urgent {
+            self.urgent_windows.push(window);
+        } else {
+            self.urgent_windows.retain(|&w| w != window);
+        }
+        true
     }
 
     /// Gets the fullscreen window
@@ -52,14 +144,72 @@ impl Workspace {
         self.zoomed_window = window;
     }
 
-    /// Gets all windows in this workspace
+    /// Gets all windows in this workspace: tiled ones in layout order, then floating ones
     pub fn get_all_windows(&self) -> Vec<Window> {
-        self.bsp_tree.all_windows()
+        let mut windows = self.bsp_tree.all_windows();
+        windows.extend(self.floating.iter().map(|g| g.window));
+        windows
     }
 
-    /// Checks if this workspace contains a window
+    /// Checks if this workspace contains a window, tiled or floating
     pub fn has_window(&self, window: Window) -> bool {
-        self.bsp_tree.has_window(window)
+        self.bsp_tree.has_window(window) || self.is_floating(window)
+    }
+
+    /// Gets the window after `current` in `get_all_windows` order (wraps around)
+    pub fn next_window(&self, current: Window) -> Option<Window> {
+        let windows = self.get_all_windows();
+        let index = windows
+            .iter()
+            .position(|&w| w == current)
+            .map_or(0, |i| i + 1);
+        windows.get(index % windows.len().max(1)).copied()
+    }
+
+    /// Gets the window before `current` in `get_all_windows` order (wraps around)
+    pub fn prev_window(&self, current: Window) -> Option<Window> {
+        let windows = self.get_all_windows();
+        let index = match windows.iter().position(|&w| w == current) {
+            Some(0) => windows.len().checked_sub(1)?,
+            Some(index) => index - 1,
+            None => 0,
+        };
+        windows.get(index).copied()
+    }
+
+    /// Checks if a window floats above the layout
+    pub fn is_floating(&self, window: Window) -> bool {
+        self.floating.iter().any(|g| g.window == window)
+    }
+
+    /// Gets the floating windows with their geometry, bottom to top
+    pub fn floating_windows(&self) -> &[WindowGeometry] {
+        &self.floating
+    }
+
+    /// Gets the geometry of a floating window, None if it is tiled or not here
+    pub fn floating_geometry(&self, window: Window) -> Option<WindowGeometry> {
+        self.floating.iter().find(|g| g.window == window).copied()
+    }
+
+    /// Gets the stored geometry of a floating window for updating it
+    pub fn floating_geometry_mut(&mut self, window: Window) -> Option<&mut WindowGeometry> {
+        self.floating.iter_mut().find(|g| g.window == window)
+    }
+
+    /// Adds a window floating on top of the others at the given geometry
+    pub fn add_floating(&mut self, geometry: WindowGeometry) {
+        self.floating.push(geometry);
+    }
+
+    /// Gets the border width a window rule set for a window
+    pub fn border_width(&self, window: Window) -> Option<u32> {
+        self.border_widths.get(&window).copied()
+    }
+
+    /// Overrides the border width of one window
+    pub fn set_border_width(&mut self, window: Window, width: u32) {
+        self.border_widths.insert(window, width);
     }
 
     /// Gets the first window in the layout, or None if empty
@@ -75,15 +225,60 @@ impl Workspace {
         self.bsp_tree.add_window(window, self.focused_window, 0.5);
     }
 
+    /// Adds a tiled window next to the focused one, on `side` of it if given
+    pub fn insert_window(&mut self, window: Window, split_ratio: f32, side: Option<Direction>) {
+        // Clear zoom when adding new window (consistent with WindowState)
+        self.zoomed_window = None;
+        self.bsp_tree
+            .insert_window(window, self.focused_window, split_ratio, side);
+    }
+
     /// Removes a window from this workspace
     pub fn remove_window(&mut self, window: Window) {
         // Clear zoom if removing the zoomed window
         if self.zoomed_window == Some(window) {
             self.zoomed_window = None;
         }
+        self.focus_history.retain(|&w| w != window);
+        self.urgent_windows.retain(|&w| w != window);
+        self.floating.retain(|g| g.window != window);
+        self.border_widths.remove(&window);
         self.bsp_tree.remove_window(window);
     }
 
+    /// Removes a window; if it had focus, focus moves on as `policy` says
+    pub fn remove_window_with_focus(&mut self, window: Window, policy: FocusOnClose) {
+        let sibling = self.bsp_tree.sibling_window(window);
+        let was_focused = self.focused_window == Some(window);
+        self.remove_window(window);
+        if was_focused {
+            self.clear_focus();
+            self.refocus(policy, sibling);
+        }
+    }
+
+    /// Focuses a window as `policy` says if nothing has focus, e.g. after its workspace was deleted
+    pub fn restore_focus(&mut self, policy: FocusOnClose) {
+        self.refocus(policy, None);
+    }
+
+    /// Picks the window to focus after the focused one went away
+    /// `sibling` is the window that took over its space, if known
+    fn refocus(&mut self, policy: FocusOnClose, sibling: Option<Window>) {
+        if self.focused_window.is_some() {
+            return;
+        }
+        let next = match policy {
+            FocusOnClose::Mru => self.mru_windows().first().copied(),
+            FocusOnClose::Sibling => sibling.or_else(|| self.mru_windows().first().copied()),
+            FocusOnClose::First => self.get_first_window(),
+            FocusOnClose::None => None,
+        };
+        if next.is_some() {
+            self.set_focused_window(next);
+        }
+    }
+
     /// Clears fullscreen state
     pub fn clear_fullscreen(&mut self) {
         self.fullscreen_window = None;
@@ -110,3 +305,118 @@ impl Default for Workspace {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mru_windows() {
+        let mut workspace = Workspace::new();
+        for window in [10, 20, 30] {
+            workspace.add_window(window);
+            workspace.set_focused_window(Some(window));
+        }
+        assert_eq!(workspace.mru_windows(), [30, 20, 10]);
+
+        workspace.set_focused_window(Some(10));
+        assert_eq!(workspace.mru_windows(), [10, 30, 20]);
+
+        assert_eq!(workspace.previous_window(), Some(30));
+
+        workspace.remove_window(30);
+        assert_eq!(workspace.mru_windows(), [10, 20]);
+        assert_eq!(workspace.previous_window(), Some(20));
+
+        // Windows never focused (restored sessions) come last
+        workspace.add_window(40);
+        assert_eq!(workspace.mru_windows(), [10, 20, 40]);
+    }
+
+    #[test]
+    fn test_floating_windows() {
+        let mut workspace = Workspace::new();
+        workspace.add_window(1);
+        workspace.set_focused_window(Some(1));
+        for window in [2, 3] {
+            workspace.add_floating(WindowGeometry {
+                window,
+                x: 10,
+                y: 10,
+                width: 100,
+                height: 100,
+            });
+        }
+        assert_eq!(workspace.get_all_windows(), [1, 2, 3]);
+        assert!(workspace.has_window(2));
+        assert!(workspace.is_floating(2) && !workspace.is_floating(1));
+        assert_eq!(workspace.next_window(3), Some(1));
+        assert_eq!(workspace.prev_window(1), Some(3));
+
+        // Focusing a floating window raises it; tiled windows still split the last leaf
+        workspace.set_focused_window(Some(2));
+        let stack: Vec<Window> = workspace
+            .floating_windows()
+            .iter()
+            .map(|g| g.window)
+            .collect();
+        assert_eq!(stack, [3, 2]);
+        workspace.add_window(4);
+        assert_eq!(workspace.bsp_tree().all_windows(), [1, 4]);
+
+        workspace.set_border_width(2, 0);
+        workspace.remove_window(2);
+        assert!(!workspace.has_window(2));
+        assert_eq!(workspace.border_width(2), None);
+    }
+
+    #[test]
+    fn test_urgent_windows() {
+        let mut workspace = Workspace::new();
+        workspace.add_window(10);
+        workspace.add_window(20);
+        assert!(workspace.set_urgent(20, true));
+        assert!(!workspace.set_urgent(20, true));
+        assert!(workspace.is_urgent(20));
+        assert_eq!(workspace.urgent_windows(), [20]);
+
+        assert!(workspace.set_urgent(20, false));
+        assert!(!workspace.is_urgent(20));
+
+        workspace.set_urgent(10, true);
+        workspace.remove_window(10);
+        assert!(workspace.urgent_windows().is_empty());
+    }
+
+    #[test]
+    fn test_focus_on_close_policies() {
+        // Layout 1 | (2 / 3), focused 3 then 1 then 2
+        let setup = || {
+            let mut workspace = Workspace::new();
+            for window in [1, 2, 3] {
+                workspace.add_window(window);
+                workspace.set_focused_window(Some(window));
+            }
+            workspace.set_focused_window(Some(1));
+            workspace.set_focused_window(Some(2));
+            workspace
+        };
+
+        let expected = [
+            (FocusOnClose::Mru, Some(1)),
+            (FocusOnClose::Sibling, Some(3)),
+            (FocusOnClose::First, Some(1)),
+            (FocusOnClose::None, None),
+        ];
+        for (policy, focus) in expected {
+            let mut workspace = setup();
+            workspace.remove_window_with_focus(2, policy);
+            assert_eq!(workspace.focused_window(), focus, "{policy:?}");
+        }
+
+        // Removing an unfocused window keeps the focus
+        let mut workspace = setup();
+        workspace.remove_window_with_focus(3, FocusOnClose::First);
+        assert_eq!(workspace.focused_window(), Some(2));
+    }
+}
@@ -1,6 +1,7 @@
 //! Workspace rendering and X11 operations
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 #[cfg(debug_assertions)]
 use tracing::debug;
 use tracing::info;
@@ -8,8 +9,9 @@ use x11rb::CURRENT_TIME;
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::*;
 
-use crate::bsp::{BspNode, BspTree, SplitDirection, dimensions};
-use crate::config::Config;
+use crate::bsp::{BspNode, BspTree, Direction, SplitDirection, dimensions};
+use crate::config::{Config, DEFAULT_MODE};
+use crate::events::{EventBus, LayoutChange, WmEvent};
 use crate::workspace::Workspace;
 
 // === Geometry Types ===
@@ -24,7 +26,7 @@ pub struct BspRect {
 }
 
 /// Represents a calculated window position and size
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct WindowGeometry {
     pub window: Window,
     pub x: i32,
@@ -45,12 +47,27 @@ pub struct LayoutParams {
 pub struct WorkspaceRenderer {
     config: Config,
     screen_num: usize,
+    /// While set, rendering and flushing are deferred to the end of the batch
+    batching: bool,
+    /// A render was skipped during the current batch
+    render_pending: bool,
+    /// Active key binding mode, which picks the focused border color
+    mode: String,
+    /// Window highlighted while cycling, drawn with the focused border instead of the focused window
+    preview: Option<Window>,
 }
 
 impl WorkspaceRenderer {
     /// Creates a new workspace renderer
     pub fn new(config: Config, screen_num: usize) -> Self {
-        Self { config, screen_num }
+        Self {
+            config,
+            screen_num,
+            batching: false,
+            render_pending: false,
+            mode: DEFAULT_MODE.to_string(),
+            preview: None,
+        }
     }
 
     /// Focuses next window in BSP order
@@ -64,7 +81,7 @@ impl WorkspaceRenderer {
         }
 
         let next_window = if let Some(current) = workspace.focused_window() {
-            workspace.bsp_tree().next_window(current).unwrap_or(current)
+            workspace.next_window(current).unwrap_or(current)
         } else {
             match workspace.get_first_window() {
                 Some(window) => window,
@@ -96,7 +113,7 @@ impl WorkspaceRenderer {
         }
 
         let prev_window = if let Some(current) = workspace.focused_window() {
-            workspace.bsp_tree().prev_window(current).unwrap_or(current)
+            workspace.prev_window(current).unwrap_or(current)
         } else {
             match workspace.get_first_window() {
                 Some(window) => window,
@@ -117,12 +134,41 @@ impl WorkspaceRenderer {
         Ok(())
     }
 
+    /// Starts deferring renders so a chain of commands hits the screen once
+    pub fn begin_batch(&mut self) {
+        self.batching = true;
+        self.render_pending = false;
+    }
+
+    /// Stops deferring renders, returning whether one was skipped
+    pub fn end_batch(&mut self) -> bool {
+        self.batching = false;
+        std::mem::take(&mut self.render_pending)
+    }
+
+    /// Records a render request; returns true if it must be deferred
+    pub fn defer_render(&mut self) -> bool {
+        if self.batching {
+            self.render_pending = true;
+        }
+        self.batching
+    }
+
+    /// Whether renders and flushes are currently deferred
+    pub fn is_batching(&self) -> bool {
+        self.batching
+    }
+
     /// Applies current workspace state to screen (unified rendering method)
     pub fn apply_workspace<C: Connection>(
         &mut self,
         conn: &mut C,
         workspace: &Workspace,
     ) -> Result<()> {
+        if self.defer_render() {
+            return Ok(());
+        }
+
         if workspace.get_all_windows().is_empty() {
             return Ok(());
         }
@@ -151,6 +197,14 @@ impl WorkspaceRenderer {
             )?;
         }
 
+        // Floating windows stay above the tiled ones, in their own stacking order
+        for floating in workspace.floating_windows() {
+            conn.configure_window(
+                floating.window,
+                &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+            )?;
+        }
+
         conn.flush()?;
 
         #[cfg(debug_assertions)]
@@ -167,53 +221,51 @@ impl WorkspaceRenderer {
         &mut self,
         conn: &mut C,
         workspace: &Workspace,
+    ) -> Result<()> {
+        for &window in &workspace.get_all_windows() {
+            conn.map_window(window)?;
+        }
+
+        self.configure_layout(conn, workspace)
+    }
+
+    /// Lays out a workspace that is not on screen, without mapping its windows
+    pub fn apply_hidden_workspace<C: Connection>(
+        &mut self,
+        conn: &mut C,
+        workspace: &Workspace,
+    ) -> Result<()> {
+        // Fullscreen geometry does not depend on the config
+        if workspace.fullscreen_window().is_some() {
+            return Ok(());
+        }
+        self.configure_layout(conn, workspace)
+    }
+
+    /// Sets borders and geometry of every tiled window in the workspace
+    fn configure_layout<C: Connection>(
+        &mut self,
+        conn: &mut C,
+        workspace: &Workspace,
     ) -> Result<()> {
         let setup = conn.setup();
         let screen = &setup.roots[self.screen_num];
 
-        let border_width = self.config.border_width();
+        let border_width = |window| {
+            workspace
+                .border_width(window)
+                .unwrap_or(self.config.border_width())
+        };
         for &window in &workspace.get_all_windows() {
-            conn.map_window(window)?;
             conn.configure_window(
                 window,
-                &ConfigureWindowAux::new().border_width(border_width),
+                &ConfigureWindowAux::new().border_width(border_width(window)),
             )?;
         }
 
-        let mut geometries = self.calculate_window_geometries(
-            workspace,
-            screen.width_in_pixels,
-            screen.height_in_pixels,
-        );
-
-        // Apply zoom if a window is zoomed
-        if let Some(zoomed_window) = workspace.zoomed_window() {
-            let screen_rect =
-                self.calculate_screen_rect(screen.width_in_pixels, screen.height_in_pixels);
-
-            // Find parent bounds for the zoomed window
-            if let Some(parent_bounds) = workspace
-                .bsp_tree()
-                .find_parent_bounds(zoomed_window, screen_rect)
-            {
-                // Override the zoomed window's geometry with parent bounds
-                for geometry in &mut geometries {
-                    if geometry.window == zoomed_window {
-                        geometry.x = parent_bounds.x;
-                        geometry.y = parent_bounds.y;
-                        geometry.width = parent_bounds
-                            .width
-                            .max(self.layout_params().min_window_width as i32)
-                            as u32;
-                        geometry.height = parent_bounds
-                            .height
-                            .max(self.layout_params().min_window_height as i32)
-                            as u32;
-                        break;
-                    }
-                }
-            }
-        }
+        let mut geometries =
+            self.layout_geometries(workspace, screen.width_in_pixels, screen.height_in_pixels);
+        geometries.extend_from_slice(workspace.floating_windows());
 
         for geometry in &geometries {
             let border_color = self.border_color_for_window(workspace, geometry.window);
@@ -228,7 +280,7 @@ impl WorkspaceRenderer {
                 .y(geometry.y)
                 .width(geometry.width)
                 .height(geometry.height)
-                .border_width(border_width);
+                .border_width(border_width(geometry.window));
 
             // If this is the zoomed window, ensure it's on top
             if Some(geometry.window) == workspace.zoomed_window() {
@@ -286,7 +338,9 @@ impl WorkspaceRenderer {
                 };
 
                 conn.send_event(false, window, EventMask::NO_EVENT, event)?;
-                conn.flush()?;
+                if !self.batching {
+                    conn.flush()?;
+                }
                 info!("Sent WM_DELETE_WINDOW message to window {:?}", window);
                 return Ok(());
             }
@@ -301,7 +355,9 @@ impl WorkspaceRenderer {
     fn kill_window_forcefully<C: Connection>(&self, conn: &C, window: Window) -> Result<()> {
         info!("Forcefully killing window {:?}", window);
         conn.kill_client(window)?;
-        conn.flush()?;
+        if !self.batching {
+            conn.flush()?;
+        }
         Ok(())
     }
 
@@ -310,6 +366,7 @@ impl WorkspaceRenderer {
         &mut self,
         conn: &mut C,
         workspace: &mut Workspace,
+        events: &mut EventBus,
     ) -> Result<()> {
         // Don't allow zoom in fullscreen mode
         if workspace.fullscreen_window().is_some() {
@@ -336,7 +393,10 @@ impl WorkspaceRenderer {
         if workspace.zoomed_window() == Some(focused) {
             // Already zoomed - unzoom
             workspace.set_zoomed_window(None);
-            info!("Unzoomed window: {:?}", focused);
+            events.emit(WmEvent::ZoomToggled {
+                window: focused,
+                enabled: false,
+            });
         } else {
             // Check if this window can be zoomed (has a parent)
             if workspace
@@ -351,7 +411,10 @@ impl WorkspaceRenderer {
 
             // Zoom the focused window
             workspace.set_zoomed_window(Some(focused));
-            info!("Zoomed window: {:?}", focused);
+            events.emit(WmEvent::ZoomToggled {
+                window: focused,
+                enabled: true,
+            });
         }
 
         // Apply the new state
@@ -364,8 +427,16 @@ impl WorkspaceRenderer {
         &mut self,
         conn: &mut C,
         workspace: &mut Workspace,
+        index: usize,
+        events: &mut EventBus,
     ) -> Result<()> {
-        self.swap_window_direction(conn, workspace, SwapDirection::Next)
+        if self.swap_window_direction(conn, workspace, SwapDirection::Next)? {
+            events.emit(WmEvent::LayoutChanged {
+                workspace: index,
+                change: LayoutChange::Swap,
+            });
+        }
+        Ok(())
     }
 
     /// Swaps the currently focused window with the previous window in the layout
@@ -373,19 +444,27 @@ impl WorkspaceRenderer {
         &mut self,
         conn: &mut C,
         workspace: &mut Workspace,
+        index: usize,
+        events: &mut EventBus,
     ) -> Result<()> {
-        self.swap_window_direction(conn, workspace, SwapDirection::Previous)
+        if self.swap_window_direction(conn, workspace, SwapDirection::Previous)? {
+            events.emit(WmEvent::LayoutChanged {
+                workspace: index,
+                change: LayoutChange::Swap,
+            });
+        }
+        Ok(())
     }
 
-    /// Helper method to swap windows in a given direction
+    /// Helper method to swap windows in a given direction, returning whether a swap happened
     fn swap_window_direction<C: Connection>(
         &mut self,
         conn: &mut C,
         workspace: &mut Workspace,
         direction: SwapDirection,
-    ) -> Result<()> {
-        if workspace.get_all_windows().len() < 2 {
-            return Ok(());
+    ) -> Result<bool> {
+        if workspace.bsp_tree().all_windows().len() < 2 {
+            return Ok(false);
         }
 
         // Exit fullscreen if active, then perform swap
@@ -395,6 +474,13 @@ impl WorkspaceRenderer {
         }
 
         if let Some(focused) = workspace.focused_window() {
+            if workspace.is_floating(focused) {
+                info!(
+                    "Floating window {:?} has no place in the layout to swap",
+                    focused
+                );
+                return Ok(false);
+            }
             let target_window = match direction {
                 SwapDirection::Next => workspace.bsp_tree().next_window(focused),
                 SwapDirection::Previous => workspace.bsp_tree().prev_window(focused),
@@ -418,9 +504,10 @@ impl WorkspaceRenderer {
 
                 // Apply complete state to screen
                 self.apply_workspace(conn, workspace)?;
+                return Ok(true);
             }
         }
-        Ok(())
+        Ok(false)
     }
 
     /// Rotates the focused window by flipping its parent split direction
@@ -428,6 +515,8 @@ impl WorkspaceRenderer {
         &mut self,
         conn: &mut C,
         workspace: &mut Workspace,
+        index: usize,
+        events: &mut EventBus,
     ) -> Result<()> {
         let focused = match workspace.focused_window() {
             Some(window) => window,
@@ -444,7 +533,7 @@ impl WorkspaceRenderer {
         }
 
         // Need at least 2 windows to rotate
-        if workspace.get_all_windows().len() < 2 {
+        if workspace.bsp_tree().all_windows().len() < 2 {
             info!("Not enough windows to rotate (need at least 2)");
             return Ok(());
         }
@@ -460,7 +549,10 @@ impl WorkspaceRenderer {
         if rotated {
             // Apply complete state to screen
             self.apply_workspace(conn, workspace)?;
-            info!("Window rotation completed for window {:?}", focused);
+            events.emit(WmEvent::LayoutChanged {
+                workspace: index,
+                change: LayoutChange::Rotate,
+            });
         } else {
             info!(
                 "No rotation performed - window {:?} may be root or not found",
@@ -471,11 +563,55 @@ impl WorkspaceRenderer {
         Ok(())
     }
 
+    /// Moves the focused window's edge in the given direction by `pixels`
+    pub fn resize_window<C: Connection>(
+        &mut self,
+        conn: &mut C,
+        workspace: &mut Workspace,
+        direction: Direction,
+        pixels: i32,
+        index: usize,
+        events: &mut EventBus,
+    ) -> Result<()> {
+        let Some(focused) = workspace.focused_window() else {
+            info!("No window focused for resize");
+            return Ok(());
+        };
+        if workspace.fullscreen_window().is_some() {
+            info!("Cannot resize in fullscreen mode");
+            return Ok(());
+        }
+
+        let setup = conn.setup();
+        let screen = &setup.roots[self.screen_num];
+        let screen_rect =
+            self.calculate_screen_rect(screen.width_in_pixels, screen.height_in_pixels);
+
+        if workspace
+            .bsp_tree_mut()
+            .resize_window(focused, direction, pixels, screen_rect)
+        {
+            self.apply_workspace(conn, workspace)?;
+            events.emit(WmEvent::LayoutChanged {
+                workspace: index,
+                change: LayoutChange::Resize,
+            });
+        } else {
+            info!(
+                "Window {:?} has no neighbour {:?} to resize into",
+                focused, direction
+            );
+        }
+        Ok(())
+    }
+
     /// Balances the BSP tree by calculating optimal split ratios based on window count
     pub fn balance_tree<C: Connection>(
         &mut self,
         conn: &mut C,
         workspace: &mut Workspace,
+        index: usize,
+        events: &mut EventBus,
     ) -> Result<()> {
         info!("Balancing BSP tree");
 
@@ -485,12 +621,86 @@ impl WorkspaceRenderer {
         // Apply the balanced layout
         self.apply_workspace(conn, workspace)?;
 
-        info!("BSP tree balanced and applied");
+        events.emit(WmEvent::LayoutChanged {
+            workspace: index,
+            change: LayoutChange::Balance,
+        });
         Ok(())
     }
 
+    /// Returns the tiled geometries for a workspace on this screen, including zoom
+    pub fn window_geometries<C: Connection>(
+        &self,
+        conn: &C,
+        workspace: &Workspace,
+    ) -> Vec<WindowGeometry> {
+        let screen = &conn.setup().roots[self.screen_num];
+        self.layout_geometries(workspace, screen.width_in_pixels, screen.height_in_pixels)
+    }
+
+    /// Gets the active configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Replaces the config used for layout and borders
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+    }
+
+    /// Sets the key binding mode whose border color marks the focused window
+    pub fn set_mode(&mut self, mode: &str) {
+        self.mode = mode.to_string();
+    }
+
+    /// Highlights a window without focusing it, or goes back to highlighting the focused one
+    pub fn set_preview(&mut self, window: Option<Window>) {
+        self.preview = window;
+    }
+
     // === Helper methods ===
 
+    /// Calculates tiled geometries and applies the zoom override (pure calculation)
+    fn layout_geometries(
+        &self,
+        workspace: &Workspace,
+        screen_width: u16,
+        screen_height: u16,
+    ) -> Vec<WindowGeometry> {
+        let mut geometries =
+            self.calculate_window_geometries(workspace, screen_width, screen_height);
+
+        // Apply zoom if a window is zoomed
+        if let Some(zoomed_window) = workspace.zoomed_window() {
+            let screen_rect = self.calculate_screen_rect(screen_width, screen_height);
+
+            // Find parent bounds for the zoomed window
+            if let Some(parent_bounds) = workspace
+                .bsp_tree()
+                .find_parent_bounds(zoomed_window, screen_rect)
+            {
+                // Override the zoomed window's geometry with parent bounds
+                for geometry in &mut geometries {
+                    if geometry.window == zoomed_window {
+                        geometry.x = parent_bounds.x;
+                        geometry.y = parent_bounds.y;
+                        geometry.width = parent_bounds
+                            .width
+                            .max(self.layout_params().min_window_width as i32)
+                            as u32;
+                        geometry.height = parent_bounds
+                            .height
+                            .max(self.layout_params().min_window_height as i32)
+                            as u32;
+                        break;
+                    }
+                }
+            }
+        }
+
+        geometries
+    }
+
     /// Calculates window geometries from the BSP tree (pure calculation - no X11 calls)
     fn calculate_window_geometries(
         &self,
@@ -526,8 +736,10 @@ impl WorkspaceRenderer {
 
     /// Returns appropriate border color based on window focus state - helper to reduce duplication
     fn border_color_for_window(&self, workspace: &Workspace, window: Window) -> u32 {
-        if Some(window) == workspace.focused_window() {
-            self.config.focused_border_color()
+        if Some(window) == self.preview.or(workspace.focused_window()) {
+            self.config.mode_focused_border_color(&self.mode)
+        } else if workspace.is_urgent(window) {
+            self.config.urgent_border_color()
         } else {
             self.config.unfocused_border_color()
         }